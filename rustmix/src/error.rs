@@ -188,3 +188,39 @@ pub struct ParseError;
 #[derive(Error, Debug)]
 #[error("Argument is required. {0}")]
 pub struct ArgumentMissingError(pub String);
+
+#[derive(Error, Debug)]
+#[error("Invalid {0} sequence at line {1}")]
+pub struct InvalidEncodingError(pub String, pub usize);
+
+#[derive(Error, Debug)]
+#[error("Error in row {0}. {1}")]
+pub struct CsvRowError(pub u64, pub String);
+
+#[derive(Error, Debug)]
+#[error("Missing columns. {0}")]
+pub struct MissingColumnsError(pub String);
+
+#[derive(Error, Debug)]
+#[error("Error in line {0}. {1}")]
+pub struct LineParseError(pub usize, pub String);
+
+#[derive(Error, Debug)]
+#[error("Path is outside of the target directory. {0}")]
+pub struct UnsafePathError(pub String);
+
+#[derive(Error, Debug)]
+#[error("Path is not valid UTF-8. {0}")]
+pub struct InvalidUtf8PathError(pub String);
+
+#[derive(Error, Debug)]
+#[error("Another instance is already running. {0}")]
+pub struct AlreadyRunningError(pub String);
+
+#[derive(Error, Debug)]
+#[error("Checksum mismatch. Expected {0}, got {1}")]
+pub struct ChecksumMismatchError(pub String, pub String);
+
+#[derive(Error, Debug)]
+#[error("Cannot read {}. {1}", .0.display())]
+pub struct WalkError(pub std::path::PathBuf, #[source] pub std::io::Error);
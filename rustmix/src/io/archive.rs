@@ -0,0 +1,613 @@
+#[cfg(all(feature = "tar", feature = "gzip"))]
+use flate2::read::GzDecoder;
+#[cfg(feature = "gzip")]
+use flate2::write::GzEncoder;
+#[cfg(feature = "tar")]
+use std::path::Component;
+use std::{
+    fmt, fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+#[cfg(any(feature = "zip", feature = "tar"))]
+use super::path::PathEx;
+use super::{
+    file,
+    path::{self, WalkOptions},
+};
+#[cfg(any(feature = "zip", feature = "tar"))]
+use crate::error::UnsafePathError;
+use crate::{
+    error::{NotFoundError, NotSupportedError},
+    string::*,
+    Result,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ArchiveFormat {
+    #[default]
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    pub fn from_path<T: AsRef<Path>>(path: T) -> Option<Self> {
+        let name = path.as_ref().file_name()?.to_str()?.to_lowercase();
+
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArchiveFormat::Zip => write!(f, "zip"),
+            ArchiveFormat::Tar => write!(f, "tar"),
+            ArchiveFormat::TarGz => write!(f, "tar.gz"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CompressionFormat {
+    #[default]
+    Gzip,
+    Zstd,
+}
+
+impl CompressionFormat {
+    pub fn from_path<T: AsRef<Path>>(path: T) -> Option<Self> {
+        match path.as_ref().extension()?.to_str()?.to_lowercase().as_str() {
+            "gz" => Some(CompressionFormat::Gzip),
+            "zst" | "zstd" => Some(CompressionFormat::Zstd),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CompressionFormat::Gzip => "gz",
+            CompressionFormat::Zstd => "zst",
+        }
+    }
+}
+
+impl fmt::Display for CompressionFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompressionFormat::Gzip => write!(f, "gzip"),
+            CompressionFormat::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub is_dir: bool,
+}
+
+#[cfg_attr(not(any(feature = "zip", feature = "tar")), allow(dead_code))]
+struct ArchiveItem {
+    path: PathBuf,
+    name: String,
+    is_dir: bool,
+}
+
+pub fn create<A: AsRef<Path>, S: AsRef<str>>(archive: A, source: S) -> Result<usize> {
+    let archive = archive.as_ref();
+    let format = ArchiveFormat::from_path(archive).ok_or(NotSupportedError)?;
+    create_as(archive, source, format)
+}
+
+#[allow(unused_variables)]
+pub fn create_as<A: AsRef<Path>, S: AsRef<str>>(
+    archive: A,
+    source: S,
+    format: ArchiveFormat,
+) -> Result<usize> {
+    let archive = archive.as_ref();
+    let items = plan_archive(archive, source.as_ref())?;
+    file::write_atomic(archive, false, |file| match format {
+        #[cfg(feature = "zip")]
+        ArchiveFormat::Zip => write_zip(file, &items),
+        #[cfg(feature = "tar")]
+        ArchiveFormat::Tar => write_tar(file, &items),
+        #[cfg(all(feature = "tar", feature = "gzip"))]
+        ArchiveFormat::TarGz => {
+            let mut encoder = GzEncoder::new(file, flate2::Compression::default());
+            write_tar(&mut encoder, &items)?;
+            encoder.finish()?;
+            Ok(())
+        }
+        #[allow(unreachable_patterns)]
+        _ => Err(NotSupportedError.into()),
+    })?;
+    Ok(items.len())
+}
+
+pub fn extract<A: AsRef<Path>, T: AsRef<Path>>(archive: A, target: T) -> Result<Vec<PathBuf>> {
+    let archive = archive.as_ref();
+    let format = ArchiveFormat::from_path(archive).ok_or(NotSupportedError)?;
+    extract_as(archive, target, format)
+}
+
+#[allow(unused_variables)]
+pub fn extract_as<A: AsRef<Path>, T: AsRef<Path>>(
+    archive: A,
+    target: T,
+    format: ArchiveFormat,
+) -> Result<Vec<PathBuf>> {
+    let target = target.as_ref();
+    fs::create_dir_all(target)?;
+    // every extracted path is checked against the resolved target
+    let root = target.canonicalize()?;
+    let file = file::open(archive)?;
+    match format {
+        #[cfg(feature = "zip")]
+        ArchiveFormat::Zip => extract_zip(file, &root),
+        #[cfg(feature = "tar")]
+        ArchiveFormat::Tar => extract_tar(file, &root),
+        #[cfg(all(feature = "tar", feature = "gzip"))]
+        ArchiveFormat::TarGz => extract_tar(GzDecoder::new(file), &root),
+        #[allow(unreachable_patterns)]
+        _ => Err(NotSupportedError.into()),
+    }
+}
+
+pub fn list<A: AsRef<Path>>(archive: A) -> Result<Vec<ArchiveEntry>> {
+    let archive = archive.as_ref();
+    let format = ArchiveFormat::from_path(archive).ok_or(NotSupportedError)?;
+    list_as(archive, format)
+}
+
+#[allow(unused_variables)]
+pub fn list_as<A: AsRef<Path>>(archive: A, format: ArchiveFormat) -> Result<Vec<ArchiveEntry>> {
+    let file = file::open(archive)?;
+    match format {
+        #[cfg(feature = "zip")]
+        ArchiveFormat::Zip => {
+            let mut archive = zip::ZipArchive::new(file)?;
+            let mut entries = Vec::with_capacity(archive.len());
+
+            for i in 0..archive.len() {
+                let entry = archive.by_index_raw(i)?;
+                entries.push(ArchiveEntry {
+                    path: PathBuf::from(entry.name().trim_end_matches('/')),
+                    size: entry.size(),
+                    is_dir: entry.is_dir(),
+                });
+            }
+
+            Ok(entries)
+        }
+        #[cfg(feature = "tar")]
+        ArchiveFormat::Tar => list_tar(file),
+        #[cfg(all(feature = "tar", feature = "gzip"))]
+        ArchiveFormat::TarGz => list_tar(GzDecoder::new(file)),
+        #[allow(unreachable_patterns)]
+        _ => Err(NotSupportedError.into()),
+    }
+}
+
+#[allow(unused_variables, unused_mut)]
+pub fn compress<R: Read, W: Write>(
+    mut reader: R,
+    writer: W,
+    format: CompressionFormat,
+    level: Option<i32>,
+) -> Result<u64> {
+    match format {
+        #[cfg(feature = "gzip")]
+        CompressionFormat::Gzip => {
+            let level = match level {
+                Some(level) => flate2::Compression::new(level.clamp(0, 9) as u32),
+                None => flate2::Compression::default(),
+            };
+            let mut encoder = GzEncoder::new(writer, level);
+            let n = io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?;
+            Ok(n)
+        }
+        #[cfg(feature = "zstd")]
+        CompressionFormat::Zstd => {
+            let mut encoder = zstd::Encoder::new(writer, level.unwrap_or(0))?;
+            let n = io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?;
+            Ok(n)
+        }
+        #[allow(unreachable_patterns)]
+        _ => Err(NotSupportedError.into()),
+    }
+}
+
+pub fn decompress<R: Read, W: Write>(
+    reader: R,
+    mut writer: W,
+    format: CompressionFormat,
+) -> Result<u64> {
+    let mut reader = decompress_reader(reader, format)?;
+    let n = io::copy(&mut reader, &mut writer)?;
+    writer.flush()?;
+    Ok(n)
+}
+
+// the encoder finishes the stream when dropped, use compress() to get the errors
+#[allow(unused_variables)]
+pub fn compress_writer<'a, W: Write + 'a>(
+    writer: W,
+    format: CompressionFormat,
+    level: Option<i32>,
+) -> Result<Box<dyn Write + 'a>> {
+    match format {
+        #[cfg(feature = "gzip")]
+        CompressionFormat::Gzip => {
+            let level = match level {
+                Some(level) => flate2::Compression::new(level.clamp(0, 9) as u32),
+                None => flate2::Compression::default(),
+            };
+            Ok(Box::new(GzEncoder::new(writer, level)))
+        }
+        #[cfg(feature = "zstd")]
+        CompressionFormat::Zstd => Ok(Box::new(
+            zstd::Encoder::new(writer, level.unwrap_or(0))?.auto_finish(),
+        )),
+        #[allow(unreachable_patterns)]
+        _ => Err(NotSupportedError.into()),
+    }
+}
+
+#[allow(unused_variables, unused_mut)]
+pub fn decompress_reader<'a, R: Read + 'a>(
+    reader: R,
+    format: CompressionFormat,
+) -> Result<Box<dyn Read + 'a>> {
+    match format {
+        #[cfg(feature = "gzip")]
+        CompressionFormat::Gzip => Ok(Box::new(flate2::read::MultiGzDecoder::new(reader))),
+        #[cfg(feature = "zstd")]
+        CompressionFormat::Zstd => Ok(Box::new(zstd::Decoder::new(reader)?)),
+        #[allow(unreachable_patterns)]
+        _ => Err(NotSupportedError.into()),
+    }
+}
+
+pub fn compress_file<F: AsRef<Path>, T: AsRef<Path>>(
+    from: F,
+    to: T,
+    format: CompressionFormat,
+    level: Option<i32>,
+) -> Result<u64> {
+    let reader = file::open(from)?;
+    let mut n = 0;
+    file::write_atomic(to, false, |file| {
+        n = compress(reader, file, format, level)?;
+        Ok(())
+    })?;
+    Ok(n)
+}
+
+pub fn decompress_file<F: AsRef<Path>, T: AsRef<Path>>(
+    from: F,
+    to: T,
+    format: CompressionFormat,
+) -> Result<u64> {
+    let reader = file::open(from)?;
+    let mut n = 0;
+    file::write_atomic(to, false, |file| {
+        n = decompress(reader, file, format)?;
+        Ok(())
+    })?;
+    Ok(n)
+}
+
+fn plan_archive(archive: &Path, source: &str) -> Result<Vec<ArchiveItem>> {
+    let sources = if source.find_first(|e| e == '*' || e == '?').is_some() {
+        path::lst_match(source)?.collect::<Vec<_>>()
+    } else if Path::new(source).exists() {
+        vec![PathBuf::from(source)]
+    } else {
+        Vec::new()
+    };
+
+    if sources.is_empty() {
+        return Err(NotFoundError(source.to_string()).into());
+    }
+
+    // an existing archive inside the source must not end up in itself
+    let archive = archive.canonicalize().ok();
+    let mut items = Vec::new();
+
+    for source in sources {
+        if archive.is_some() && source.canonicalize().ok() == archive {
+            continue;
+        }
+
+        let Some(name) = source.file_name() else {
+            continue;
+        };
+        let name = name.to_string_lossy().into_owned();
+
+        if !source.is_dir() {
+            items.push(ArchiveItem {
+                path: source,
+                name,
+                is_dir: false,
+            });
+            continue;
+        }
+
+        items.push(ArchiveItem {
+            path: source.clone(),
+            name: name.clone(),
+            is_dir: true,
+        });
+
+        for entry in path::walk_with(&source, &WalkOptions::new().with_sorted(true))? {
+            let entry = entry?;
+
+            if archive.is_some() && entry.path.canonicalize().ok() == archive {
+                continue;
+            }
+
+            let relative = entry.path.strip_prefix(&source)?;
+            let mut entry_name = name.clone();
+
+            for component in relative.components() {
+                entry_name.push('/');
+                entry_name.push_str(&component.as_os_str().to_string_lossy());
+            }
+
+            items.push(ArchiveItem {
+                is_dir: entry.is_dir(),
+                path: entry.path,
+                name: entry_name,
+            });
+        }
+    }
+
+    Ok(items)
+}
+
+#[cfg(feature = "zip")]
+fn write_zip<W: Write + io::Seek>(writer: W, items: &[ArchiveItem]) -> Result<()> {
+    let mut zip = zip::ZipWriter::new(writer);
+
+    for item in items {
+        // links are stored as links, the same as in tar
+        let metadata = fs::symlink_metadata(&item.path)?;
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .large_file(metadata.len() >= u32::MAX as u64);
+        #[cfg(unix)]
+        let options = {
+            use std::os::unix::fs::PermissionsExt;
+            options.unix_permissions(metadata.permissions().mode())
+        };
+
+        if item.is_dir {
+            zip.add_directory(item.name.as_str(), options)?;
+            continue;
+        }
+
+        if metadata.file_type().is_symlink() {
+            let target = fs::read_link(&item.path)?;
+            zip.add_symlink(
+                item.name.as_str(),
+                target.to_string_lossy().into_owned(),
+                options,
+            )?;
+            continue;
+        }
+
+        zip.start_file(item.name.as_str(), options)?;
+        let mut file = fs::File::open(&item.path)?;
+        io::copy(&mut file, &mut zip)?;
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
+#[cfg(feature = "zip")]
+fn extract_zip<R: Read + io::Seek>(reader: R, root: &Path) -> Result<Vec<PathBuf>> {
+    let mut archive = zip::ZipArchive::new(reader)?;
+    let mut extracted = Vec::with_capacity(archive.len());
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let path = root.safe_join(entry.name())?;
+
+        if entry.is_dir() {
+            fs::create_dir_all(&path)?;
+            extracted.push(path);
+            continue;
+        }
+
+        // links could point anywhere, they are not extracted
+        if entry.is_symlink() {
+            continue;
+        }
+
+        ensure_parent(root, &path)?;
+        let mut file = fs::File::create(&path)?;
+        io::copy(&mut entry, &mut file)?;
+
+        #[cfg(unix)]
+        if let Some(mode) = entry.unix_mode() {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode & 0o777))?;
+        }
+
+        extracted.push(path);
+    }
+
+    Ok(extracted)
+}
+
+#[cfg(feature = "tar")]
+fn write_tar<W: Write>(writer: W, items: &[ArchiveItem]) -> Result<()> {
+    let mut tar = tar::Builder::new(writer);
+    tar.follow_symlinks(false);
+
+    for item in items {
+        if item.is_dir {
+            tar.append_dir(&item.name, &item.path)?;
+        } else {
+            tar.append_path_with_name(&item.path, &item.name)?;
+        }
+    }
+
+    tar.finish()?;
+    Ok(())
+}
+
+#[cfg(feature = "tar")]
+fn extract_tar<R: Read>(reader: R, root: &Path) -> Result<Vec<PathBuf>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut extracted = Vec::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.into_owned();
+        let path = root.safe_join(&name)?;
+        let kind = entry.header().entry_type();
+
+        if kind.is_dir() {
+            fs::create_dir_all(&path)?;
+            extracted.push(path);
+            continue;
+        }
+
+        if !kind.is_symlink() && !kind.is_hard_link() {
+            if !kind.is_file() && !kind.is_contiguous() {
+                continue;
+            }
+
+            ensure_parent(root, &path)?;
+            entry.unpack(&path)?;
+            extracted.push(path);
+            continue;
+        }
+
+        let link = entry
+            .link_name()?
+            .ok_or_else(|| UnsafePathError(name.to_string_lossy().into_owned()))?
+            .into_owned();
+        ensure_parent(root, &path)?;
+
+        if kind.is_symlink() {
+            // symlinks resolve from their own folder as it is on disk
+            let parent = match path.parent() {
+                Some(parent) => parent.canonicalize()?,
+                None => root.to_path_buf(),
+            };
+            resolve_link(root, &parent, &link)?;
+            entry.unpack(&path)?;
+        } else {
+            // hard links resolve from the archive root and must end up on a file in it
+            let source = resolve_link(root, root, &link)?.canonicalize()?;
+
+            if !source.starts_with(root) || !source.is_file() {
+                return Err(UnsafePathError(link.to_string_lossy().into_owned()).into());
+            }
+
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+
+            fs::hard_link(&source, &path)?;
+        }
+
+        extracted.push(path);
+    }
+
+    Ok(extracted)
+}
+
+#[cfg(feature = "tar")]
+fn list_tar<R: Read>(reader: R) -> Result<Vec<ArchiveEntry>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = Vec::new();
+
+    for entry in archive.entries()? {
+        let entry = entry?;
+        entries.push(ArchiveEntry {
+            path: entry.path()?.into_owned(),
+            size: entry.size(),
+            is_dir: entry.header().entry_type().is_dir(),
+        });
+    }
+
+    Ok(entries)
+}
+
+// follows a link target through what is already on disk. a missing folder followed by `..`
+// could still become a symlink through a later entry, so it is refused as well.
+#[cfg(feature = "tar")]
+fn resolve_link(root: &Path, base: &Path, link: &Path) -> Result<PathBuf> {
+    let unsafe_link = || UnsafePathError(link.to_string_lossy().into_owned());
+    let mut resolved = base.to_path_buf();
+    let mut missing = false;
+
+    for component in link.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if !missing => {
+                resolved.pop();
+            }
+            Component::Normal(part) => {
+                resolved.push(part);
+
+                if missing {
+                    continue;
+                }
+
+                match fs::symlink_metadata(&resolved) {
+                    Ok(metadata) if metadata.file_type().is_symlink() => {
+                        resolved = resolved.canonicalize().map_err(|_| unsafe_link())?;
+                    }
+                    Ok(_) => {}
+                    Err(_) => missing = true,
+                }
+            }
+            _ => return Err(unsafe_link().into()),
+        }
+
+        if !resolved.starts_with(root) {
+            return Err(unsafe_link().into());
+        }
+    }
+
+    Ok(resolved)
+}
+
+// catches folders that were replaced with symlinks by earlier entries
+#[cfg(any(feature = "zip", feature = "tar"))]
+fn ensure_parent(root: &Path, path: &Path) -> Result<()> {
+    let Some(parent) = path.parent() else {
+        return Ok(());
+    };
+    fs::create_dir_all(parent)?;
+
+    if !parent.canonicalize()?.starts_with(root) {
+        return Err(UnsafePathError(path.to_string_lossy().into_owned()).into());
+    }
+
+    if path.is_symlink() {
+        fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
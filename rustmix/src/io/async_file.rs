@@ -0,0 +1,507 @@
+use csv::WriterBuilder;
+use futures::{Future, Stream};
+use serde::{de, Serialize};
+use std::path::Path;
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, SeekFrom},
+};
+
+use super::encoding::Encoding;
+use super::file::{
+    self as sync_file, FileOpenOptions, DELIMITER_DEFAULT, ENCODING_SAMPLE_SIZE,
+    LINES_BUFFER_DEFAULT,
+};
+use crate::{
+    error::{CsvRowError, InvalidEncodingError, LineParseError},
+    Result,
+};
+
+pub async fn open<T: AsRef<Path>>(path: T) -> Result<File> {
+    File::open(path).await.map_err(Into::into)
+}
+
+pub async fn create<T: AsRef<Path>>(path: T) -> Result<File> {
+    create_with(path, FileOpenOptions::Default).await
+}
+
+pub async fn create_with<T: AsRef<Path>>(path: T, options: FileOpenOptions) -> Result<File> {
+    let path = path.as_ref();
+
+    if let Some(dir) = path.parent() {
+        if !dir.as_os_str().is_empty() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+    }
+
+    let mut opt = OpenOptions::new();
+    opt.read(true);
+    match options {
+        FileOpenOptions::New => opt.create_new(true),
+        FileOpenOptions::Truncate => opt.create(true).truncate(true),
+        _ => opt.create(true).append(true),
+    };
+    opt.write(true);
+    opt.open(path).await.map_err(Into::into)
+}
+
+pub trait AsyncFileEx {
+    fn read(&mut self) -> impl Stream<Item = Result<String>> + '_;
+    fn read_filtered<F: Fn(&str) -> bool + Send + 'static>(
+        &mut self,
+        filter: F,
+    ) -> impl Stream<Item = Result<String>> + '_;
+    fn read_batch<R: Fn(u32, Vec<String>) -> bool + Send + 'static>(
+        &mut self,
+        batch: usize,
+        callback: R,
+    ) -> impl Future<Output = Result<u32>> + Send;
+    fn read_batch_filtered<
+        F: Fn(&str) -> bool + Send + 'static,
+        R: Fn(u32, Vec<String>) -> bool + Send + 'static,
+    >(
+        &mut self,
+        batch: usize,
+        filter: F,
+        callback: R,
+    ) -> impl Future<Output = Result<u32>> + Send;
+    fn write<T: AsRef<str> + Sync>(&mut self, data: &T) -> impl Future<Output = Result<()>> + Send;
+    fn write_lines<T: AsRef<str> + Send, I: Iterator<Item = T> + Send>(
+        &mut self,
+        data: I,
+    ) -> impl Future<Output = Result<()>> + Send;
+    fn read_json<T: de::DeserializeOwned>(&mut self) -> impl Future<Output = Result<T>> + Send;
+    fn write_json<T: Serialize + Sync>(
+        &mut self,
+        data: &T,
+        pretty: Option<bool>,
+    ) -> impl Future<Output = Result<()>> + Send;
+    fn read_jsonl<T: de::DeserializeOwned>(&mut self) -> impl Stream<Item = Result<T>> + '_;
+    fn write_jsonl<T: Serialize + Sync>(
+        &mut self,
+        data: &[T],
+    ) -> impl Future<Output = Result<()>> + Send;
+    fn append_jsonl<T: Serialize + Sync>(
+        &mut self,
+        data: &T,
+    ) -> impl Future<Output = Result<()>> + Send;
+    #[cfg(feature = "yaml")]
+    fn read_yaml<T: de::DeserializeOwned>(&mut self) -> impl Future<Output = Result<T>> + Send;
+    #[cfg(feature = "yaml")]
+    fn write_yaml<T: Serialize + Sync>(
+        &mut self,
+        data: &T,
+    ) -> impl Future<Output = Result<()>> + Send;
+    #[cfg(feature = "toml")]
+    fn read_toml<T: de::DeserializeOwned>(&mut self) -> impl Future<Output = Result<T>> + Send;
+    #[cfg(feature = "toml")]
+    fn write_toml<T: Serialize + Sync>(
+        &mut self,
+        data: &T,
+        pretty: Option<bool>,
+    ) -> impl Future<Output = Result<()>> + Send;
+    fn sniff_delimiter(&mut self) -> impl Future<Output = Result<u8>> + Send;
+    fn read_csv<T: de::DeserializeOwned>(
+        &mut self,
+        delimiter: Option<u8>,
+        has_headers: Option<bool>,
+    ) -> impl Future<Output = Result<impl Iterator<Item = Result<T>>>> + Send;
+    fn read_csv_batch<T: de::DeserializeOwned + Send, R: Fn(u32, Vec<T>) -> bool + Send + 'static>(
+        &mut self,
+        batch: usize,
+        delimiter: Option<u8>,
+        has_headers: Option<bool>,
+        callback: R,
+    ) -> impl Future<Output = Result<u32>> + Send;
+    fn read_csv_batch_filtered<
+        T: de::DeserializeOwned + Send,
+        F: Fn(&T) -> bool + Send + 'static,
+        R: Fn(u32, Vec<T>) -> bool + Send + 'static,
+    >(
+        &mut self,
+        batch: usize,
+        delimiter: Option<u8>,
+        has_headers: Option<bool>,
+        filter: F,
+        callback: R,
+    ) -> impl Future<Output = Result<u32>> + Send;
+    fn write_csv<T: Serialize + Sync>(
+        &mut self,
+        data: &[T],
+        delimiter: Option<u8>,
+        has_headers: Option<bool>,
+    ) -> impl Future<Output = Result<()>> + Send;
+}
+
+impl AsyncFileEx for File {
+    fn read(&mut self) -> impl Stream<Item = Result<String>> + '_ {
+        self.read_filtered(|_| true)
+    }
+
+    fn read_filtered<F: Fn(&str) -> bool + Send + 'static>(
+        &mut self,
+        filter: F,
+    ) -> impl Stream<Item = Result<String>> + '_ {
+        let reader = BufReader::new(self);
+        // the reader is dropped after an io error so the stream ends there
+        futures::stream::unfold(Some((reader, filter, 0usize)), |state| async move {
+            let (mut reader, filter, mut line_number) = state?;
+
+            loop {
+                let mut buffer = Vec::new();
+
+                match reader.read_until(b'\n', &mut buffer).await {
+                    Ok(0) => return None,
+                    Ok(_) => {
+                        line_number += 1;
+                        trim_line_end(&mut buffer);
+
+                        match decode_line(buffer, line_number) {
+                            Ok(line) if line.is_empty() || !filter(&line) => {}
+                            item => return Some((item, Some((reader, filter, line_number)))),
+                        }
+                    }
+                    Err(e) => return Some((Err(e.into()), None)),
+                }
+            }
+        })
+    }
+
+    async fn read_batch<R: Fn(u32, Vec<String>) -> bool + Send + 'static>(
+        &mut self,
+        batch: usize,
+        callback: R,
+    ) -> Result<u32> {
+        self.read_batch_filtered(batch, |_| true, callback).await
+    }
+
+    async fn read_batch_filtered<
+        F: Fn(&str) -> bool + Send + 'static,
+        R: Fn(u32, Vec<String>) -> bool + Send + 'static,
+    >(
+        &mut self,
+        batch: usize,
+        filter: F,
+        callback: R,
+    ) -> Result<u32> {
+        let batch = if batch == 0 {
+            LINES_BUFFER_DEFAULT
+        } else {
+            batch
+        };
+        let mut reader = BufReader::new(self);
+        let mut batch_number = 0u32;
+        let mut line_number = 0usize;
+        let mut buffer = Vec::new();
+        let mut lines = Vec::with_capacity(batch);
+
+        loop {
+            let n = reader.read_until(b'\n', &mut buffer).await?;
+
+            if n == 0 {
+                break;
+            }
+
+            line_number += 1;
+            let line = decode_line(std::mem::take(&mut buffer), line_number)?;
+
+            // lines keep their line ending in batches
+            if line.trim_end_matches(['\r', '\n']).is_empty() || !filter(&line) {
+                continue;
+            }
+
+            lines.push(line);
+
+            if lines.len() < batch {
+                continue;
+            }
+
+            batch_number += 1;
+            let contin = callback(batch_number, std::mem::take(&mut lines));
+
+            if !contin {
+                return Ok(batch_number);
+            }
+        }
+
+        if lines.is_empty() {
+            return Ok(batch_number);
+        }
+
+        batch_number += 1;
+        callback(batch_number, lines);
+        Ok(batch_number)
+    }
+
+    async fn write<T: AsRef<str> + Sync>(&mut self, data: &T) -> Result<()> {
+        let line = format!("{}\n", data.as_ref());
+        self.write_all(line.as_bytes()).await.map_err(Into::into)
+    }
+
+    async fn write_lines<T: AsRef<str> + Send, I: Iterator<Item = T> + Send>(
+        &mut self,
+        data: I,
+    ) -> Result<()> {
+        for line in data {
+            let line = format!("{}\n", line.as_ref());
+            self.write_all(line.as_bytes()).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn read_json<T: de::DeserializeOwned>(&mut self) -> Result<T> {
+        let content = read_content(self).await?;
+        let data: T = serde_json::from_slice(&content)?;
+        Ok(data)
+    }
+
+    async fn write_json<T: Serialize + Sync>(
+        &mut self,
+        data: &T,
+        pretty: Option<bool>,
+    ) -> Result<()> {
+        let serialized = match pretty {
+            Some(true) => serde_json::to_vec_pretty(data)?,
+            _ => serde_json::to_vec(data)?,
+        };
+        self.write_all(&serialized).await?;
+        Ok(())
+    }
+
+    fn read_jsonl<T: de::DeserializeOwned>(&mut self) -> impl Stream<Item = Result<T>> + '_ {
+        let reader = BufReader::new(self);
+        // a line that does not parse is reported and skipped, a read error ends the stream
+        futures::stream::unfold(Some((reader, 0usize)), |state| async move {
+            let (mut reader, mut n) = state?;
+
+            loop {
+                let mut line = String::new();
+
+                match reader.read_line(&mut line).await {
+                    Ok(0) => return None,
+                    Ok(_) => {
+                        n += 1;
+
+                        if line.trim().is_empty() {
+                            continue;
+                        }
+
+                        let item = serde_json::from_str::<T>(&line)
+                            .map_err(|e| LineParseError(n, e.to_string()).into());
+                        return Some((item, Some((reader, n))));
+                    }
+                    Err(e) => return Some((Err(e.into()), None)),
+                }
+            }
+        })
+    }
+
+    async fn write_jsonl<T: Serialize + Sync>(&mut self, data: &[T]) -> Result<()> {
+        let mut serialized = Vec::new();
+
+        for item in data {
+            serde_json::to_writer(&mut serialized, item)?;
+            serialized.push(b'\n');
+        }
+
+        self.write_all(&serialized).await?;
+        self.flush().await?;
+        Ok(())
+    }
+
+    async fn append_jsonl<T: Serialize + Sync>(&mut self, data: &T) -> Result<()> {
+        let mut serialized = serde_json::to_vec(data)?;
+        serialized.push(b'\n');
+        self.seek(SeekFrom::End(0)).await?;
+        self.write_all(&serialized).await?;
+        Ok(())
+    }
+
+    #[cfg(feature = "yaml")]
+    async fn read_yaml<T: de::DeserializeOwned>(&mut self) -> Result<T> {
+        let content = read_content(self).await?;
+        let data: T = serde_yaml::from_slice(&content)?;
+        Ok(data)
+    }
+
+    #[cfg(feature = "yaml")]
+    async fn write_yaml<T: Serialize + Sync>(&mut self, data: &T) -> Result<()> {
+        let serialized = serde_yaml::to_string(data)?;
+        self.write_all(serialized.as_bytes()).await?;
+        Ok(())
+    }
+
+    #[cfg(feature = "toml")]
+    async fn read_toml<T: de::DeserializeOwned>(&mut self) -> Result<T> {
+        let mut content = String::new();
+        self.read_to_string(&mut content).await?;
+        let data: T = toml::from_str(&content)?;
+        Ok(data)
+    }
+
+    #[cfg(feature = "toml")]
+    async fn write_toml<T: Serialize + Sync>(
+        &mut self,
+        data: &T,
+        pretty: Option<bool>,
+    ) -> Result<()> {
+        let serialized = match pretty {
+            Some(true) => toml::to_string_pretty(data)?,
+            _ => toml::to_string(data)?,
+        };
+        self.write_all(serialized.as_bytes()).await?;
+        Ok(())
+    }
+
+    async fn sniff_delimiter(&mut self) -> Result<u8> {
+        let position = self.stream_position().await?;
+        let mut sample = Vec::with_capacity(ENCODING_SAMPLE_SIZE);
+        (&mut *self)
+            .take(ENCODING_SAMPLE_SIZE as u64)
+            .read_to_end(&mut sample)
+            .await?;
+        self.seek(SeekFrom::Start(position)).await?;
+        Ok(sync_file::sniff_delimiter(&sample))
+    }
+
+    // the records are parsed lazily from a std handle that shares the file position
+    async fn read_csv<T: de::DeserializeOwned>(
+        &mut self,
+        delimiter: Option<u8>,
+        has_headers: Option<bool>,
+    ) -> Result<impl Iterator<Item = Result<T>>> {
+        let delimiter = match delimiter {
+            Some(delimiter) => delimiter,
+            None => self.sniff_delimiter().await?,
+        };
+        let file = self.try_clone().await?.into_std().await;
+        let reader = sync_file::create_typed_reader::<T, _>(file, delimiter, has_headers)?;
+        let first_row = if reader.has_headers() { 2u64 } else { 1u64 };
+        Ok(reader
+            .into_deserialize::<T>()
+            .enumerate()
+            .map(move |(i, e)| e.map_err(|e| sync_file::csv_row_error(e, first_row + i as u64))))
+    }
+
+    async fn read_csv_batch<
+        T: de::DeserializeOwned + Send,
+        R: Fn(u32, Vec<T>) -> bool + Send + 'static,
+    >(
+        &mut self,
+        batch: usize,
+        delimiter: Option<u8>,
+        has_headers: Option<bool>,
+        callback: R,
+    ) -> Result<u32> {
+        self.read_csv_batch_filtered(batch, delimiter, has_headers, |_: &T| true, callback)
+            .await
+    }
+
+    async fn read_csv_batch_filtered<
+        T: de::DeserializeOwned + Send,
+        F: Fn(&T) -> bool + Send + 'static,
+        R: Fn(u32, Vec<T>) -> bool + Send + 'static,
+    >(
+        &mut self,
+        batch: usize,
+        delimiter: Option<u8>,
+        has_headers: Option<bool>,
+        filter: F,
+        callback: R,
+    ) -> Result<u32> {
+        let batch = if batch == 0 {
+            LINES_BUFFER_DEFAULT
+        } else {
+            batch
+        };
+        let mut batch_number = 0u32;
+        let mut records: Vec<T> = Vec::with_capacity(batch);
+
+        for record in self.read_csv::<T>(delimiter, has_headers).await? {
+            let record = record?;
+
+            if !filter(&record) {
+                continue;
+            }
+
+            records.push(record);
+
+            if records.len() < batch {
+                continue;
+            }
+
+            batch_number += 1;
+            let contin = callback(batch_number, std::mem::take(&mut records));
+
+            if !contin {
+                return Ok(batch_number);
+            }
+        }
+
+        if records.is_empty() {
+            return Ok(batch_number);
+        }
+
+        batch_number += 1;
+        callback(batch_number, records);
+        Ok(batch_number)
+    }
+
+    async fn write_csv<T: Serialize + Sync>(
+        &mut self,
+        data: &[T],
+        delimiter: Option<u8>,
+        has_headers: Option<bool>,
+    ) -> Result<()> {
+        let serialized = {
+            let delimiter = delimiter.unwrap_or(DELIMITER_DEFAULT);
+            let has_headers = has_headers.unwrap_or(true);
+            let mut writer = WriterBuilder::new()
+                .delimiter(delimiter)
+                .has_headers(has_headers)
+                .from_writer(Vec::new());
+
+            let first_row = if has_headers { 2u64 } else { 1u64 };
+
+            for (i, record) in data.iter().enumerate() {
+                writer
+                    .serialize(record)
+                    .map_err(|e| CsvRowError(first_row + i as u64, e.to_string()))?;
+            }
+
+            writer.into_inner().map_err(|e| e.into_error())?
+        };
+        self.write_all(&serialized).await?;
+        self.flush().await?;
+        Ok(())
+    }
+}
+
+async fn read_content(file: &mut File) -> std::io::Result<Vec<u8>> {
+    let mut content = Vec::new();
+    file.read_to_end(&mut content).await?;
+    Ok(content)
+}
+
+fn trim_line_end(buffer: &mut Vec<u8>) {
+    if buffer.ends_with(b"\n") {
+        buffer.pop();
+
+        if buffer.ends_with(b"\r") {
+            buffer.pop();
+        }
+    }
+}
+
+// same rules as the sync LineReader with Encoding::Utf8 and DecodePolicy::Report
+fn decode_line(mut buffer: Vec<u8>, line_number: usize) -> Result<String> {
+    let bom = Encoding::Utf8.bom();
+
+    if line_number == 1 && buffer.starts_with(bom) {
+        buffer.drain(..bom.len());
+    }
+
+    String::from_utf8(buffer)
+        .map_err(|_| InvalidEncodingError(Encoding::Utf8.to_string(), line_number).into())
+}
@@ -1,12 +1,65 @@
-use std::fs;
+#[cfg(feature = "threading")]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 pub use std::path::{Path, PathBuf};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    fmt, fs,
+    sync::Mutex,
+};
 
-use crate::Result;
+use super::path::{self, WalkEntry, WalkOptions};
+use crate::{error::InvalidDirectoryError, threading::HumanBytes, Result};
+
+const LARGEST_DEF: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileUsage {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtensionUsage {
+    // lower case and without the dot, empty for files without an extension
+    pub extension: String,
+    pub files: u64,
+    pub size: u64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiskUsage {
+    pub root: PathBuf,
+    pub size: u64,
+    pub files: u64,
+    pub directories: u64,
+    // entries that could not be read, they are left out of the totals
+    pub errors: u64,
+    pub largest: Vec<FileUsage>,
+    pub extensions: Vec<ExtensionUsage>,
+}
+
+impl fmt::Display for DiskUsage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} in {} files, {} directories",
+            HumanBytes(self.size),
+            self.files,
+            self.directories
+        )
+    }
+}
 
 pub fn current() -> PathBuf {
     std::env::current_dir().unwrap()
 }
 
+pub fn try_current() -> Result<PathBuf> {
+    std::env::current_dir().map_err(|e| InvalidDirectoryError(e.to_string()).into())
+}
+
 pub fn exists<T: AsRef<Path>>(path: T) -> bool {
     path.as_ref().is_dir()
 }
@@ -34,3 +87,164 @@ pub fn ensure<T: AsRef<Path>>(path: T) -> Result<()> {
 pub fn is_empty<T: AsRef<Path>>(path: T) -> bool {
     fs::read_dir(path.as_ref()).map_or(false, |mut i| i.next().is_none())
 }
+
+pub fn usage<T: AsRef<Path>>(path: T) -> Result<DiskUsage> {
+    usage_with(path, &WalkOptions::new(), LARGEST_DEF)
+}
+
+pub fn usage_with<T: AsRef<Path>>(
+    path: T,
+    options: &WalkOptions,
+    largest: usize,
+) -> Result<DiskUsage> {
+    let path = path.as_ref();
+    let tally = Mutex::new(UsageTally::new(largest));
+    let count = |entry: Result<WalkEntry>| match entry {
+        Ok(entry) if entry.is_dir() => tally.lock().unwrap().report.directories += 1,
+        Ok(entry) => {
+            let size = entry.metadata().ok().map(|m| m.len());
+            tally.lock().unwrap().add(entry.path, size);
+        }
+        Err(_) => tally.lock().unwrap().report.errors += 1,
+    };
+
+    #[cfg(feature = "threading")]
+    path::walk_par(path, options, count)?;
+    #[cfg(not(feature = "threading"))]
+    path::walk_with(path, options)?.for_each(count);
+
+    let mut report = tally.into_inner().unwrap().finish();
+    report.root = path.to_path_buf();
+    Ok(report)
+}
+
+pub fn usage_match<T: AsRef<str>>(pattern: T, largest: usize) -> Result<DiskUsage> {
+    let pattern = pattern.as_ref();
+    let mut files = Vec::new();
+    let mut directories = 0u64;
+
+    for path in path::lst_match(pattern)? {
+        // glob follows symlinks, so they are measured by their targets
+        match fs::metadata(&path) {
+            Ok(metadata) if metadata.is_dir() => directories += 1,
+            metadata => files.push(WalkEntry {
+                file_type: metadata.ok().map(|m| m.file_type()),
+                path,
+                depth: 0,
+                is_symlink: false,
+            }),
+        }
+    }
+
+    let mut tally = UsageTally::new(largest);
+
+    for (path, size) in measure(files) {
+        tally.add(path, size);
+    }
+
+    let mut report = tally.finish();
+    report.root = pattern_root(pattern);
+    report.directories = directories;
+    Ok(report)
+}
+
+// the part of the pattern before the first wildcard
+fn pattern_root(pattern: &str) -> PathBuf {
+    Path::new(pattern)
+        .components()
+        .take_while(|e| !e.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+        .collect()
+}
+
+#[cfg(feature = "threading")]
+fn measure(entries: Vec<WalkEntry>) -> Vec<(PathBuf, Option<u64>)> {
+    entries
+        .into_par_iter()
+        .map(|e| {
+            let size = e.metadata().ok().map(|m| m.len());
+            (e.path, size)
+        })
+        .collect()
+}
+
+#[cfg(not(feature = "threading"))]
+fn measure(entries: Vec<WalkEntry>) -> Vec<(PathBuf, Option<u64>)> {
+    entries
+        .into_iter()
+        .map(|e| {
+            let size = e.metadata().ok().map(|m| m.len());
+            (e.path, size)
+        })
+        .collect()
+}
+
+struct UsageTally {
+    report: DiskUsage,
+    largest: usize,
+    // a min-heap keeps only the largest files around
+    heap: BinaryHeap<Reverse<(u64, PathBuf)>>,
+    extensions: HashMap<String, (u64, u64)>,
+}
+
+impl UsageTally {
+    fn new(largest: usize) -> Self {
+        UsageTally {
+            report: DiskUsage::default(),
+            largest,
+            heap: BinaryHeap::with_capacity(largest + 1),
+            extensions: HashMap::new(),
+        }
+    }
+
+    fn add(&mut self, path: PathBuf, size: Option<u64>) {
+        let Some(size) = size else {
+            self.report.errors += 1;
+            return;
+        };
+        self.report.size += size;
+        self.report.files += 1;
+
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let usage = self.extensions.entry(extension).or_default();
+        usage.0 += 1;
+        usage.1 += size;
+
+        if self.largest == 0 {
+            return;
+        }
+
+        self.heap.push(Reverse((size, path)));
+
+        if self.heap.len() > self.largest {
+            self.heap.pop();
+        }
+    }
+
+    fn finish(self) -> DiskUsage {
+        let mut report = self.report;
+        report.largest = self
+            .heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((size, path))| FileUsage { path, size })
+            .collect();
+        report.extensions = self
+            .extensions
+            .into_iter()
+            .map(|(extension, (files, size))| ExtensionUsage {
+                extension,
+                files,
+                size,
+            })
+            .collect();
+        report.extensions.sort_by(|a, b| {
+            b.size
+                .cmp(&a.size)
+                .then_with(|| a.extension.cmp(&b.extension))
+        });
+        report
+    }
+}
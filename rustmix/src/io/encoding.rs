@@ -0,0 +1,287 @@
+use std::{fmt, io::BufRead};
+
+use crate::{error::InvalidEncodingError, Result};
+
+const SAMPLE_SIZE: usize = 4096;
+const BOM_UTF8: &[u8] = &[0xEF, 0xBB, 0xBF];
+const BOM_UTF16LE: &[u8] = &[0xFF, 0xFE];
+const BOM_UTF16BE: &[u8] = &[0xFE, 0xFF];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Utf16LE,
+    Utf16BE,
+    Latin1,
+}
+
+impl Encoding {
+    pub fn bom(&self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8 => BOM_UTF8,
+            Encoding::Utf16LE => BOM_UTF16LE,
+            Encoding::Utf16BE => BOM_UTF16BE,
+            Encoding::Latin1 => &[],
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Encoding::Utf8 => write!(f, "UTF-8"),
+            Encoding::Utf16LE => write!(f, "UTF-16LE"),
+            Encoding::Utf16BE => write!(f, "UTF-16BE"),
+            Encoding::Latin1 => write!(f, "ISO-8859-1"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DecodePolicy {
+    #[default]
+    Replace,
+    Report,
+}
+
+impl fmt::Display for DecodePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodePolicy::Replace => write!(f, "Replace"),
+            DecodePolicy::Report => write!(f, "Report"),
+        }
+    }
+}
+
+pub fn detect(sample: &[u8]) -> (Encoding, usize) {
+    if sample.starts_with(BOM_UTF8) {
+        return (Encoding::Utf8, BOM_UTF8.len());
+    }
+
+    if sample.starts_with(BOM_UTF16LE) {
+        return (Encoding::Utf16LE, BOM_UTF16LE.len());
+    }
+
+    if sample.starts_with(BOM_UTF16BE) {
+        return (Encoding::Utf16BE, BOM_UTF16BE.len());
+    }
+
+    let sample = &sample[..sample.len().min(SAMPLE_SIZE)];
+
+    if sample.len() >= 2 {
+        let mut even = 0usize;
+        let mut odd = 0usize;
+
+        for (i, b) in sample.iter().enumerate() {
+            if *b != 0 {
+                continue;
+            }
+
+            if i % 2 == 0 {
+                even += 1;
+            } else {
+                odd += 1;
+            }
+        }
+
+        // ASCII text in UTF-16 has a zero byte in every other position
+        let threshold = sample.len() / 4;
+
+        if odd > threshold && even == 0 {
+            return (Encoding::Utf16LE, 0);
+        }
+
+        if even > threshold && odd == 0 {
+            return (Encoding::Utf16BE, 0);
+        }
+    }
+
+    match std::str::from_utf8(sample) {
+        Ok(_) => (Encoding::Utf8, 0),
+        // the sample may end in the middle of a multi-byte sequence
+        Err(e) if e.error_len().is_none() => (Encoding::Utf8, 0),
+        Err(_) => (Encoding::Latin1, 0),
+    }
+}
+
+pub fn decode(bytes: &[u8], encoding: Encoding, policy: DecodePolicy) -> Option<String> {
+    match encoding {
+        Encoding::Utf8 => match std::str::from_utf8(bytes) {
+            Ok(s) => Some(s.to_string()),
+            Err(_) if policy == DecodePolicy::Replace => {
+                Some(String::from_utf8_lossy(bytes).into_owned())
+            }
+            Err(_) => None,
+        },
+        Encoding::Latin1 => Some(bytes.iter().map(|b| *b as char).collect()),
+        Encoding::Utf16LE | Encoding::Utf16BE => {
+            let units = bytes.chunks_exact(2).map(|e| match encoding {
+                Encoding::Utf16LE => u16::from_le_bytes([e[0], e[1]]),
+                _ => u16::from_be_bytes([e[0], e[1]]),
+            });
+            let mut text = String::with_capacity(bytes.len() / 2);
+
+            for c in char::decode_utf16(units) {
+                match c {
+                    Ok(c) => text.push(c),
+                    Err(_) if policy == DecodePolicy::Replace => {
+                        text.push(char::REPLACEMENT_CHARACTER)
+                    }
+                    Err(_) => return None,
+                }
+            }
+
+            if !bytes.len().is_multiple_of(2) {
+                if policy == DecodePolicy::Report {
+                    return None;
+                }
+
+                text.push(char::REPLACEMENT_CHARACTER);
+            }
+
+            Some(text)
+        }
+    }
+}
+
+pub struct LineReader<R: BufRead> {
+    reader: R,
+    encoding: Encoding,
+    policy: DecodePolicy,
+    line: usize,
+    buffer: Vec<u8>,
+    done: bool,
+}
+
+impl<R: BufRead> LineReader<R> {
+    pub fn new(reader: R, policy: DecodePolicy) -> Result<Self> {
+        Self::with_encoding(reader, None, policy)
+    }
+
+    pub fn with_encoding(
+        mut reader: R,
+        encoding: Option<Encoding>,
+        policy: DecodePolicy,
+    ) -> Result<Self> {
+        let sample = reader.fill_buf()?;
+        let (detected, bom) = detect(sample);
+        let (encoding, bom) = match encoding {
+            Some(encoding) if encoding != detected => {
+                // only skip the BOM if it belongs to the requested encoding
+                let bom = encoding.bom();
+
+                if !bom.is_empty() && sample.starts_with(bom) {
+                    (encoding, bom.len())
+                } else {
+                    (encoding, 0)
+                }
+            }
+            _ => (detected, bom),
+        };
+        reader.consume(bom);
+        Ok(Self {
+            reader,
+            encoding,
+            policy,
+            line: 0,
+            buffer: Vec::new(),
+            done: false,
+        })
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn line_number(&self) -> usize {
+        self.line
+    }
+
+    fn read_raw_line(&mut self) -> std::io::Result<usize> {
+        self.buffer.clear();
+
+        match self.encoding {
+            Encoding::Utf8 | Encoding::Latin1 => self.reader.read_until(b'\n', &mut self.buffer),
+            Encoding::Utf16LE | Encoding::Utf16BE => loop {
+                let n = self.reader.read_until(b'\n', &mut self.buffer)?;
+
+                if n == 0 || self.buffer.last() != Some(&b'\n') {
+                    break Ok(self.buffer.len());
+                }
+
+                let len = self.buffer.len();
+
+                match self.encoding {
+                    // '\n' is [0x0A, 0x00]; the newline byte must start a code unit
+                    Encoding::Utf16LE if len % 2 == 1 => {
+                        let Some(next) = self.reader.fill_buf()?.first().copied() else {
+                            break Ok(len);
+                        };
+                        self.reader.consume(1);
+                        self.buffer.push(next);
+
+                        if next == 0 {
+                            break Ok(self.buffer.len());
+                        }
+                    }
+                    // '\n' is [0x00, 0x0A]; the newline byte must end a code unit
+                    Encoding::Utf16BE if len.is_multiple_of(2) && self.buffer[len - 2] == 0 => {
+                        break Ok(self.buffer.len());
+                    }
+                    _ => {}
+                }
+            },
+        }
+    }
+
+    fn trim_line_end(&mut self) {
+        let (newline, carriage): (&[u8], &[u8]) = match self.encoding {
+            Encoding::Utf8 | Encoding::Latin1 => (b"\n", b"\r"),
+            Encoding::Utf16LE => (&[0x0A, 0x00], &[0x0D, 0x00]),
+            Encoding::Utf16BE => (&[0x00, 0x0A], &[0x00, 0x0D]),
+        };
+
+        if self.buffer.ends_with(newline) {
+            self.buffer.truncate(self.buffer.len() - newline.len());
+
+            if self.buffer.ends_with(carriage) {
+                self.buffer.truncate(self.buffer.len() - carriage.len());
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for LineReader<R> {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.read_raw_line() {
+            Ok(0) => {
+                self.done = true;
+                None
+            }
+            Ok(_) => {
+                self.line += 1;
+                self.trim_line_end();
+
+                match decode(&self.buffer, self.encoding, self.policy) {
+                    Some(line) => Some(Ok(line)),
+                    None => Some(Err(InvalidEncodingError(
+                        self.encoding.to_string(),
+                        self.line,
+                    )
+                    .into())),
+                }
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e.into()))
+            }
+        }
+    }
+}
@@ -2,15 +2,39 @@ use csv::{ReaderBuilder, WriterBuilder};
 use serde::{de, Serialize};
 use serde_json;
 use std::{
+    fmt,
     fs::{self, OpenOptions},
-    io::{BufRead, BufReader, Write},
-    path::Path,
+    io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+    result::Result as StdResult,
+    time::Duration,
 };
 
-use super::directory;
-use crate::Result;
+#[cfg(feature = "hash")]
+use super::hash::{self, HashAlgorithm};
+#[cfg(feature = "mmap")]
+use super::lines::MappedLines;
+use super::{
+    directory,
+    encoding::{self, DecodePolicy, Encoding, LineReader},
+    lines::{self, LineIndex},
+    lock::{self, FileLock, LockMode},
+    tail::{FollowOptions, Follower},
+};
+use crate::{
+    error::{
+        CsvRowError, InvalidOperationError, LineParseError, MissingColumnsError, NotSupportedError,
+    },
+    random, Result,
+};
 
-const LINES_BUFFER_DEFAULT: usize = 1000;
+pub(crate) const LINES_BUFFER_DEFAULT: usize = 1000;
+const TEMP_NAME_LEN: usize = 8;
+pub(crate) const ENCODING_SAMPLE_SIZE: usize = 4096;
+const DELIMITER_SAMPLE_LINES: usize = 10;
+pub(crate) const DELIMITER_DEFAULT: u8 = b',';
+const DELIMITER_CANDIDATES: [u8; 4] = [b',', b'\t', b';', b'|'];
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum FileOpenOptions {
@@ -21,6 +45,45 @@ pub enum FileOpenOptions {
     Append,
 }
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DataFormat {
+    #[default]
+    Json,
+    JsonLines,
+    Yaml,
+    Toml,
+}
+
+impl DataFormat {
+    pub fn from_extension<T: AsRef<str>>(extension: T) -> Option<Self> {
+        match extension.as_ref().to_lowercase().as_str() {
+            "json" => Some(DataFormat::Json),
+            "jsonl" | "ndjson" => Some(DataFormat::JsonLines),
+            "yaml" | "yml" => Some(DataFormat::Yaml),
+            "toml" => Some(DataFormat::Toml),
+            _ => None,
+        }
+    }
+
+    pub fn from_path<T: AsRef<Path>>(path: T) -> Option<Self> {
+        path.as_ref()
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(Self::from_extension)
+    }
+}
+
+impl fmt::Display for DataFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DataFormat::Json => write!(f, "JSON"),
+            DataFormat::JsonLines => write!(f, "JSON Lines"),
+            DataFormat::Yaml => write!(f, "YAML"),
+            DataFormat::Toml => write!(f, "TOML"),
+        }
+    }
+}
+
 pub fn exists<T: AsRef<Path>>(path: T) -> bool {
     path.as_ref().is_file()
 }
@@ -56,6 +119,200 @@ pub fn from_options<T: AsRef<Path>>(path: T, options: &OpenOptions) -> Result<st
     options.open(path).map_err(Into::into)
 }
 
+#[derive(Debug)]
+pub struct AtomicFile {
+    file: Option<std::fs::File>,
+    temp: PathBuf,
+    target: PathBuf,
+    backup: bool,
+    committed: bool,
+}
+
+impl AtomicFile {
+    pub fn new<T: AsRef<Path>>(path: T) -> Result<Self> {
+        Self::with_backup(path, false)
+    }
+
+    pub fn with_backup<T: AsRef<Path>>(path: T, backup: bool) -> Result<Self> {
+        let target = path.as_ref().to_path_buf();
+        let name = target
+            .file_name()
+            .ok_or_else(|| InvalidOperationError("Path has no file name".to_string()))?
+            .to_string_lossy()
+            .into_owned();
+        let dir = match target.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        directory::ensure(&dir)?;
+
+        // the temp file must live on the same file system for the rename to be atomic
+        let temp = dir.join(format!(
+            ".{}.{}.tmp",
+            name,
+            random::alphanum_str(TEMP_NAME_LEN)
+        ));
+        let file = create_with(&temp, FileOpenOptions::New)?;
+        Ok(Self {
+            file: Some(file),
+            temp,
+            target,
+            backup,
+            committed: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.target
+    }
+
+    pub fn temp_path(&self) -> &Path {
+        &self.temp
+    }
+
+    pub fn backup_path(&self) -> PathBuf {
+        backup_path(&self.target)
+    }
+
+    pub fn commit(mut self) -> Result<()> {
+        let Some(mut file) = self.file.take() else {
+            return Err(InvalidOperationError("File is already committed".to_string()).into());
+        };
+        file.flush()?;
+        file.sync_all()?;
+        drop(file);
+
+        if self.target.is_file() {
+            let permissions = fs::metadata(&self.target)?.permissions();
+            fs::set_permissions(&self.temp, permissions)?;
+
+            if self.backup {
+                let backup = self.backup_path();
+
+                if backup.exists() {
+                    fs::remove_file(&backup)?;
+                }
+
+                if fs::hard_link(&self.target, &backup).is_err() {
+                    fs::copy(&self.target, &backup)?;
+                }
+            }
+        }
+
+        fs::rename(&self.temp, &self.target)?;
+        self.committed = true;
+        sync_parent(&self.target)
+    }
+}
+
+impl Deref for AtomicFile {
+    type Target = std::fs::File;
+
+    fn deref(&self) -> &Self::Target {
+        self.file.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for AtomicFile {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.file.as_mut().unwrap()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        // the temp file goes away whenever the commit did not get as far as the rename
+        drop(self.file.take());
+
+        if !self.committed {
+            let _ = fs::remove_file(&self.temp);
+        }
+    }
+}
+
+pub fn create_atomic<T: AsRef<Path>>(path: T) -> Result<AtomicFile> {
+    AtomicFile::new(path)
+}
+
+pub fn write_atomic<T: AsRef<Path>, F: FnOnce(&mut std::fs::File) -> Result<()>>(
+    path: T,
+    backup: bool,
+    write: F,
+) -> Result<()> {
+    let mut file = AtomicFile::with_backup(path, backup)?;
+    write(&mut file)?;
+    file.commit()
+}
+
+pub fn backup_path<T: AsRef<Path>>(path: T) -> PathBuf {
+    let path = path.as_ref();
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+#[cfg(unix)]
+pub(crate) fn sync_parent(path: &Path) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    std::fs::File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn sync_parent(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+pub fn load<T: de::DeserializeOwned, P: AsRef<Path>>(path: P) -> Result<T> {
+    let path = path.as_ref();
+    let format = DataFormat::from_path(path).ok_or(NotSupportedError)?;
+    load_as(path, format)
+}
+
+pub fn load_as<T: de::DeserializeOwned, P: AsRef<Path>>(path: P, format: DataFormat) -> Result<T> {
+    let file = open(path)?;
+    match format {
+        DataFormat::Json => file.read_json(),
+        DataFormat::JsonLines => {
+            let items = file
+                .read_jsonl::<serde_json::Value>()?
+                .collect::<Result<Vec<_>>>()?;
+            serde_json::from_value(serde_json::Value::Array(items)).map_err(Into::into)
+        }
+        #[cfg(feature = "yaml")]
+        DataFormat::Yaml => file.read_yaml(),
+        #[cfg(feature = "toml")]
+        DataFormat::Toml => file.read_toml(),
+        #[allow(unreachable_patterns)]
+        _ => Err(NotSupportedError.into()),
+    }
+}
+
+pub fn save<T: Serialize, P: AsRef<Path>>(path: P, data: &T) -> Result<()> {
+    let path = path.as_ref();
+    let format = DataFormat::from_path(path).ok_or(NotSupportedError)?;
+    save_as(path, data, format)
+}
+
+pub fn save_as<T: Serialize, P: AsRef<Path>>(path: P, data: &T, format: DataFormat) -> Result<()> {
+    write_atomic(path, false, |file| match format {
+        DataFormat::Json => file.write_json(data, Some(true)),
+        DataFormat::JsonLines => match serde_json::to_value(data)? {
+            serde_json::Value::Array(items) => file.write_jsonl(&items),
+            value => file.append_jsonl(&value),
+        },
+        #[cfg(feature = "yaml")]
+        DataFormat::Yaml => file.write_yaml(data),
+        #[cfg(feature = "toml")]
+        DataFormat::Toml => file.write_toml(data, Some(true)),
+        #[allow(unreachable_patterns)]
+        _ => Err(NotSupportedError.into()),
+    })
+}
+
 pub fn delete<T: AsRef<Path>>(path: T) -> Result<()> {
     let path = path.as_ref();
 
@@ -67,11 +324,31 @@ pub fn delete<T: AsRef<Path>>(path: T) -> Result<()> {
 }
 
 pub trait FileEx {
-    fn read(&self) -> Result<impl Iterator<Item = String>>;
+    fn read(&self) -> Result<impl Iterator<Item = Result<String>>>;
     fn read_filtered<F: Fn(&str) -> bool + 'static>(
         &self,
         filter: F,
-    ) -> Result<impl Iterator<Item = String>>;
+    ) -> Result<impl Iterator<Item = Result<String>>>;
+    fn detect_encoding(&self) -> Result<Encoding>;
+    fn acquire_lock(&self, mode: LockMode) -> Result<FileLock<'_>>;
+    fn try_acquire_lock(&self, mode: LockMode) -> Result<Option<FileLock<'_>>>;
+    fn acquire_lock_timeout(&self, mode: LockMode, timeout: Duration) -> Result<FileLock<'_>>;
+    fn follow<P: AsRef<Path>>(&self, path: P, options: &FollowOptions) -> Result<Follower>;
+    #[cfg(feature = "hash")]
+    fn hash(&self, algorithm: HashAlgorithm) -> Result<String>;
+    fn read_rev(&self) -> Result<impl Iterator<Item = Result<String>>>;
+    fn read_last(&self, count: usize) -> Result<Vec<String>>;
+    fn index_lines(&self, step: usize) -> Result<LineIndex>;
+    /// # Safety
+    ///
+    /// See [`MappedLines::from_file`].
+    #[cfg(feature = "mmap")]
+    unsafe fn map_lines(&self, step: usize) -> Result<MappedLines>;
+    fn read_decoded(
+        &self,
+        encoding: Option<Encoding>,
+        policy: DecodePolicy,
+    ) -> Result<impl Iterator<Item = Result<String>>>;
     fn read_batch<R: Fn(u32, Vec<String>) -> bool + 'static>(
         &self,
         batch: usize,
@@ -90,6 +367,17 @@ pub trait FileEx {
     fn write_lines<T: AsRef<str>>(&mut self, data: impl Iterator<Item = T>) -> Result<()>;
     fn read_json<T: de::DeserializeOwned>(&self) -> Result<T>;
     fn write_json<T: Serialize>(&mut self, data: &T, pretty: Option<bool>) -> Result<()>;
+    fn read_jsonl<T: de::DeserializeOwned>(&self) -> Result<impl Iterator<Item = Result<T>>>;
+    fn write_jsonl<T: Serialize>(&mut self, data: &[T]) -> Result<()>;
+    fn append_jsonl<T: Serialize>(&mut self, data: &T) -> Result<()>;
+    #[cfg(feature = "yaml")]
+    fn read_yaml<T: de::DeserializeOwned>(&self) -> Result<T>;
+    #[cfg(feature = "yaml")]
+    fn write_yaml<T: Serialize>(&mut self, data: &T) -> Result<()>;
+    #[cfg(feature = "toml")]
+    fn read_toml<T: de::DeserializeOwned>(&self) -> Result<T>;
+    #[cfg(feature = "toml")]
+    fn write_toml<T: Serialize>(&mut self, data: &T, pretty: Option<bool>) -> Result<()>;
     fn create_delimited_reader(
         &mut self,
         delimiter: Option<u8>,
@@ -100,32 +388,117 @@ pub trait FileEx {
         delimiter: Option<u8>,
         has_headers: Option<bool>,
     ) -> csv::Writer<&mut std::fs::File>;
+    fn sniff_delimiter(&self) -> Result<u8>;
+    fn read_csv<T: de::DeserializeOwned>(
+        &self,
+        delimiter: Option<u8>,
+        has_headers: Option<bool>,
+    ) -> Result<impl Iterator<Item = Result<T>>>;
+    fn read_csv_batch<T: de::DeserializeOwned, R: Fn(u32, Vec<T>) -> bool + 'static>(
+        &self,
+        batch: usize,
+        delimiter: Option<u8>,
+        has_headers: Option<bool>,
+        callback: R,
+    ) -> Result<u32>;
+    fn read_csv_batch_filtered<
+        T: de::DeserializeOwned,
+        F: Fn(&T) -> bool + 'static,
+        R: Fn(u32, Vec<T>) -> bool + 'static,
+    >(
+        &self,
+        batch: usize,
+        delimiter: Option<u8>,
+        has_headers: Option<bool>,
+        filter: F,
+        callback: R,
+    ) -> Result<u32>;
+    fn write_csv<T: Serialize>(
+        &mut self,
+        data: &[T],
+        delimiter: Option<u8>,
+        has_headers: Option<bool>,
+    ) -> Result<()>;
 }
 
 impl FileEx for std::fs::File {
-    fn read(&self) -> Result<impl Iterator<Item = String>> {
+    // invalid UTF-8 is reported per line, reading stops at the first I/O error
+    fn read(&self) -> Result<impl Iterator<Item = Result<String>>> {
         let reader = BufReader::new(self);
-        Ok(reader.lines().filter_map(|line| line.ok()))
+        LineReader::with_encoding(reader, Some(Encoding::Utf8), DecodePolicy::Report)
     }
 
     fn read_filtered<F: Fn(&str) -> bool + 'static>(
         &self,
         filter: F,
-    ) -> Result<impl Iterator<Item = String>> {
-        let reader = BufReader::new(self);
-        Ok(reader.lines().filter_map(move |e| {
-            if let Ok(line) = e {
-                if line.is_empty() || !filter(&line) {
-                    return None;
-                }
-
-                Some(line)
-            } else {
-                None
-            }
+    ) -> Result<impl Iterator<Item = Result<String>>> {
+        Ok(self.read()?.filter(move |e| match e {
+            Ok(line) => !line.is_empty() && filter(line),
+            Err(_) => true,
         }))
     }
 
+    fn detect_encoding(&self) -> Result<Encoding> {
+        let sample = read_sample(self, ENCODING_SAMPLE_SIZE)?;
+        let (encoding, _) = encoding::detect(&sample);
+        Ok(encoding)
+    }
+
+    fn acquire_lock(&self, mode: LockMode) -> Result<FileLock<'_>> {
+        lock::lock(self, mode)
+    }
+
+    fn try_acquire_lock(&self, mode: LockMode) -> Result<Option<FileLock<'_>>> {
+        lock::try_lock(self, mode)
+    }
+
+    fn acquire_lock_timeout(&self, mode: LockMode, timeout: Duration) -> Result<FileLock<'_>> {
+        lock::lock_timeout(self, mode, timeout)
+    }
+
+    // follows from the current position, the path is used to pick up rotated files
+    fn follow<P: AsRef<Path>>(&self, path: P, options: &FollowOptions) -> Result<Follower> {
+        Follower::from_file(self.try_clone()?, path, options)
+    }
+
+    #[cfg(feature = "hash")]
+    fn hash(&self, algorithm: HashAlgorithm) -> Result<String> {
+        let mut file = self;
+        let position = file.stream_position()?;
+        file.seek(SeekFrom::Start(0))?;
+        let hash = hash::hash_reader(file, algorithm);
+        file.seek(SeekFrom::Start(position))?;
+        hash
+    }
+
+    fn read_rev(&self) -> Result<impl Iterator<Item = Result<String>>> {
+        lines::read_rev(self)
+    }
+
+    fn read_last(&self, count: usize) -> Result<Vec<String>> {
+        lines::read_last(self, count)
+    }
+
+    fn index_lines(&self, step: usize) -> Result<LineIndex> {
+        let mut file = self;
+        file.seek(SeekFrom::Start(0))?;
+        LineIndex::build(file, step)
+    }
+
+    #[cfg(feature = "mmap")]
+    unsafe fn map_lines(&self, step: usize) -> Result<MappedLines> {
+        MappedLines::from_file(self, step)
+    }
+
+    fn read_decoded(
+        &self,
+        encoding: Option<Encoding>,
+        policy: DecodePolicy,
+    ) -> Result<impl Iterator<Item = Result<String>>> {
+        let reader = BufReader::new(self);
+        LineReader::with_encoding(reader, encoding, policy)
+    }
+
     fn read_batch<R: Fn(u32, Vec<String>) -> bool + 'static>(
         &self,
         batch: usize,
@@ -141,7 +514,9 @@ impl FileEx for std::fs::File {
         let mut line: String = String::new();
         let mut lines: Vec<String> = Vec::with_capacity(batch);
 
-        while let Ok(n) = reader.read_line(&mut line) {
+        loop {
+            let n = reader.read_line(&mut line)?;
+
             if n == 0 {
                 break;
             }
@@ -195,7 +570,9 @@ impl FileEx for std::fs::File {
         let mut line: String = String::new();
         let mut lines = Vec::with_capacity(batch);
 
-        while let Ok(n) = reader.read_line(&mut line) {
+        loop {
+            let n = reader.read_line(&mut line)?;
+
             if n == 0 {
                 break;
             }
@@ -258,6 +635,73 @@ impl FileEx for std::fs::File {
         Ok(())
     }
 
+    fn read_jsonl<T: de::DeserializeOwned>(&self) -> Result<impl Iterator<Item = Result<T>>> {
+        let reader = BufReader::new(self);
+        Ok(reader
+            .lines()
+            .enumerate()
+            .filter_map(|(i, line)| match line {
+                Ok(line) if line.trim().is_empty() => None,
+                Ok(line) => Some(
+                    serde_json::from_str::<T>(&line)
+                        .map_err(|e| LineParseError(i + 1, e.to_string()).into()),
+                ),
+                Err(e) => Some(Err(e.into())),
+            }))
+    }
+
+    fn write_jsonl<T: Serialize>(&mut self, data: &[T]) -> Result<()> {
+        let mut writer = std::io::BufWriter::new(self);
+
+        for item in data {
+            serde_json::to_writer(&mut writer, item)?;
+            writer.write_all(b"\n")?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn append_jsonl<T: Serialize>(&mut self, data: &T) -> Result<()> {
+        let mut serialized = serde_json::to_vec(data)?;
+        serialized.push(b'\n');
+        self.seek(SeekFrom::End(0))?;
+        self.write_all(&serialized)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "yaml")]
+    fn read_yaml<T: de::DeserializeOwned>(&self) -> Result<T> {
+        let reader = BufReader::new(self);
+        let data: T = serde_yaml::from_reader(reader)?;
+        Ok(data)
+    }
+
+    #[cfg(feature = "yaml")]
+    fn write_yaml<T: Serialize>(&mut self, data: &T) -> Result<()> {
+        let serialized = serde_yaml::to_string(data)?;
+        self.write_all(serialized.as_bytes())?;
+        Ok(())
+    }
+
+    #[cfg(feature = "toml")]
+    fn read_toml<T: de::DeserializeOwned>(&self) -> Result<T> {
+        let mut content = String::new();
+        BufReader::new(self).read_to_string(&mut content)?;
+        let data: T = toml::from_str(&content)?;
+        Ok(data)
+    }
+
+    #[cfg(feature = "toml")]
+    fn write_toml<T: Serialize>(&mut self, data: &T, pretty: Option<bool>) -> Result<()> {
+        let serialized = match pretty {
+            Some(true) => toml::to_string_pretty(data)?,
+            _ => toml::to_string(data)?,
+        };
+        self.write_all(serialized.as_bytes())?;
+        Ok(())
+    }
+
     fn create_delimited_reader(
         &mut self,
         delimiter: Option<u8>,
@@ -283,4 +727,545 @@ impl FileEx for std::fs::File {
             .has_headers(has_headers)
             .from_writer(self)
     }
+
+    fn sniff_delimiter(&self) -> Result<u8> {
+        let sample = read_sample(self, ENCODING_SAMPLE_SIZE)?;
+        Ok(sniff_delimiter(&sample))
+    }
+
+    fn read_csv<T: de::DeserializeOwned>(
+        &self,
+        delimiter: Option<u8>,
+        has_headers: Option<bool>,
+    ) -> Result<impl Iterator<Item = Result<T>>> {
+        let delimiter = match delimiter {
+            Some(delimiter) => delimiter,
+            None => self.sniff_delimiter()?,
+        };
+        let reader = create_typed_reader::<T, _>(self, delimiter, has_headers)?;
+        let first_row = if reader.has_headers() { 2u64 } else { 1u64 };
+        Ok(reader
+            .into_deserialize::<T>()
+            .enumerate()
+            .map(move |(i, e)| e.map_err(|e| csv_row_error(e, first_row + i as u64))))
+    }
+
+    fn read_csv_batch<T: de::DeserializeOwned, R: Fn(u32, Vec<T>) -> bool + 'static>(
+        &self,
+        batch: usize,
+        delimiter: Option<u8>,
+        has_headers: Option<bool>,
+        callback: R,
+    ) -> Result<u32> {
+        self.read_csv_batch_filtered(batch, delimiter, has_headers, |_: &T| true, callback)
+    }
+
+    fn read_csv_batch_filtered<
+        T: de::DeserializeOwned,
+        F: Fn(&T) -> bool + 'static,
+        R: Fn(u32, Vec<T>) -> bool + 'static,
+    >(
+        &self,
+        batch: usize,
+        delimiter: Option<u8>,
+        has_headers: Option<bool>,
+        filter: F,
+        callback: R,
+    ) -> Result<u32> {
+        let batch = if batch == 0 {
+            LINES_BUFFER_DEFAULT
+        } else {
+            batch
+        };
+        let mut batch_number = 0u32;
+        let mut records: Vec<T> = Vec::with_capacity(batch);
+
+        for record in self.read_csv::<T>(delimiter, has_headers)? {
+            let record = record?;
+
+            if !filter(&record) {
+                continue;
+            }
+
+            records.push(record);
+
+            if records.len() < batch {
+                continue;
+            }
+
+            batch_number += 1;
+            let contin = callback(batch_number, std::mem::take(&mut records));
+
+            if !contin {
+                return Ok(batch_number);
+            }
+        }
+
+        if records.is_empty() {
+            return Ok(batch_number);
+        }
+
+        batch_number += 1;
+        callback(batch_number, records);
+        Ok(batch_number)
+    }
+
+    fn write_csv<T: Serialize>(
+        &mut self,
+        data: &[T],
+        delimiter: Option<u8>,
+        has_headers: Option<bool>,
+    ) -> Result<()> {
+        let delimiter = delimiter.unwrap_or(DELIMITER_DEFAULT);
+        let has_headers = has_headers.unwrap_or(true);
+        let mut writer = WriterBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(has_headers)
+            .from_writer(self);
+
+        // rows are numbered the way read_csv numbers them, the header being row 1
+        let first_row = if has_headers { 2u64 } else { 1u64 };
+
+        for (i, record) in data.iter().enumerate() {
+            writer
+                .serialize(record)
+                .map_err(|e| CsvRowError(first_row + i as u64, e.to_string()))?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn read_sample(file: &std::fs::File, size: usize) -> Result<Vec<u8>> {
+    let mut file = file;
+    let position = file.stream_position()?;
+    let mut sample = Vec::with_capacity(size);
+    file.take(size as u64).read_to_end(&mut sample)?;
+    file.seek(SeekFrom::Start(position))?;
+    Ok(sample)
+}
+
+pub(crate) fn sniff_delimiter(sample: &[u8]) -> u8 {
+    let mut lines: Vec<&[u8]> = sample
+        .split(|e| *e == b'\n')
+        .filter(|e| !e.is_empty())
+        .take(DELIMITER_SAMPLE_LINES + 1)
+        .collect();
+
+    // the last line is most likely cut in the middle
+    if lines.len() > 1 && !sample.ends_with(b"\n") {
+        lines.pop();
+    }
+
+    lines.truncate(DELIMITER_SAMPLE_LINES);
+
+    if lines.is_empty() {
+        return DELIMITER_DEFAULT;
+    }
+
+    let mut best = DELIMITER_DEFAULT;
+    let mut best_score = 0usize;
+
+    for candidate in DELIMITER_CANDIDATES {
+        let counts: Vec<usize> = lines
+            .iter()
+            .map(|line| count_unquoted(line, candidate))
+            .collect();
+        let first = counts[0];
+
+        if first == 0 {
+            continue;
+        }
+
+        // a consistent number of fields per line beats a higher but irregular count
+        let score = if counts.iter().all(|e| *e == first) {
+            first * lines.len() * 2
+        } else {
+            counts.iter().sum::<usize>()
+        };
+
+        if score > best_score {
+            best = candidate;
+            best_score = score;
+        }
+    }
+
+    best
+}
+
+fn count_unquoted(line: &[u8], delimiter: u8) -> usize {
+    let mut quoted = false;
+    let mut count = 0usize;
+
+    for b in line {
+        if *b == b'"' {
+            quoted = !quoted;
+        } else if *b == delimiter && !quoted {
+            count += 1;
+        }
+    }
+
+    count
+}
+
+pub(crate) fn create_typed_reader<T: de::DeserializeOwned, R: Read>(
+    reader: R,
+    delimiter: u8,
+    has_headers: Option<bool>,
+) -> Result<csv::Reader<R>> {
+    let has_headers = has_headers.unwrap_or(true);
+    let mut reader = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(has_headers)
+        .from_reader(reader);
+
+    if !has_headers {
+        return Ok(reader);
+    }
+
+    let fields = required_fields::<T>()?;
+
+    if fields.is_empty() {
+        return Ok(reader);
+    }
+
+    let headers = reader.headers().map_err(|e| csv_row_error(e, 1))?;
+    let missing: Vec<&str> = fields
+        .iter()
+        // csv matches the headers as they are, so they are compared untrimmed
+        .filter(|field| !headers.iter().any(|header| header == **field))
+        .copied()
+        .collect();
+
+    if !missing.is_empty() {
+        return Err(MissingColumnsError(missing.join(", ")).into());
+    }
+
+    Ok(reader)
+}
+
+pub(crate) fn csv_row_error(error: csv::Error, row: u64) -> Box<dyn std::error::Error> {
+    CsvRowError(row, error.to_string()).into()
+}
+
+// serde reports missing fields through de::Error::missing_field, so they are caught there
+#[derive(Debug)]
+enum ProbeError {
+    MissingField(&'static str),
+    InvalidField(&'static str),
+    Other(String),
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProbeError::MissingField(field) => write!(f, "missing field {}", field),
+            ProbeError::InvalidField(field) => write!(f, "invalid field {}", field),
+            ProbeError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ProbeError {}
+
+impl de::Error for ProbeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        ProbeError::Other(msg.to_string())
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        ProbeError::MissingField(field)
+    }
+}
+
+fn required_fields<T: de::DeserializeOwned>() -> Result<Vec<&'static str>> {
+    // text some common types parse from, a field that rejects one gets the next
+    const PLACEHOLDERS: [&str; 9] = [
+        "",
+        "0",
+        "1970-01-01T00:00:00Z",
+        "1970-01-01",
+        "00:00:00",
+        "1970-01-01 00:00:00",
+        "127.0.0.1",
+        "00000000-0000-0000-0000-000000000000",
+        "http://localhost/",
+    ];
+
+    #[derive(Clone, Copy)]
+    struct Probe(&'static str);
+
+    impl<'de> de::IntoDeserializer<'de, ProbeError> for Probe {
+        type Deserializer = Probe;
+
+        fn into_deserializer(self) -> Self::Deserializer {
+            self
+        }
+    }
+
+    // deserializes any requested type from a placeholder value so missing fields can be found
+    impl<'de> de::Deserializer<'de> for Probe {
+        type Error = ProbeError;
+
+        fn deserialize_any<V: de::Visitor<'de>>(self, v: V) -> StdResult<V::Value, Self::Error> {
+            v.visit_unit()
+        }
+
+        fn deserialize_bool<V: de::Visitor<'de>>(self, v: V) -> StdResult<V::Value, Self::Error> {
+            v.visit_bool(false)
+        }
+
+        fn deserialize_i8<V: de::Visitor<'de>>(self, v: V) -> StdResult<V::Value, Self::Error> {
+            v.visit_i64(0)
+        }
+
+        fn deserialize_i16<V: de::Visitor<'de>>(self, v: V) -> StdResult<V::Value, Self::Error> {
+            v.visit_i64(0)
+        }
+
+        fn deserialize_i32<V: de::Visitor<'de>>(self, v: V) -> StdResult<V::Value, Self::Error> {
+            v.visit_i64(0)
+        }
+
+        fn deserialize_i64<V: de::Visitor<'de>>(self, v: V) -> StdResult<V::Value, Self::Error> {
+            v.visit_i64(0)
+        }
+
+        fn deserialize_u8<V: de::Visitor<'de>>(self, v: V) -> StdResult<V::Value, Self::Error> {
+            v.visit_u64(0)
+        }
+
+        fn deserialize_u16<V: de::Visitor<'de>>(self, v: V) -> StdResult<V::Value, Self::Error> {
+            v.visit_u64(0)
+        }
+
+        fn deserialize_u32<V: de::Visitor<'de>>(self, v: V) -> StdResult<V::Value, Self::Error> {
+            v.visit_u64(0)
+        }
+
+        fn deserialize_u64<V: de::Visitor<'de>>(self, v: V) -> StdResult<V::Value, Self::Error> {
+            v.visit_u64(0)
+        }
+
+        fn deserialize_f32<V: de::Visitor<'de>>(self, v: V) -> StdResult<V::Value, Self::Error> {
+            v.visit_f64(0.0)
+        }
+
+        fn deserialize_f64<V: de::Visitor<'de>>(self, v: V) -> StdResult<V::Value, Self::Error> {
+            v.visit_f64(0.0)
+        }
+
+        fn deserialize_char<V: de::Visitor<'de>>(self, v: V) -> StdResult<V::Value, Self::Error> {
+            v.visit_char(' ')
+        }
+
+        fn deserialize_str<V: de::Visitor<'de>>(self, v: V) -> StdResult<V::Value, Self::Error> {
+            v.visit_str(self.0)
+        }
+
+        fn deserialize_string<V: de::Visitor<'de>>(self, v: V) -> StdResult<V::Value, Self::Error> {
+            v.visit_str(self.0)
+        }
+
+        fn deserialize_bytes<V: de::Visitor<'de>>(self, v: V) -> StdResult<V::Value, Self::Error> {
+            v.visit_bytes(&[])
+        }
+
+        fn deserialize_byte_buf<V: de::Visitor<'de>>(
+            self,
+            v: V,
+        ) -> StdResult<V::Value, Self::Error> {
+            v.visit_bytes(&[])
+        }
+
+        fn deserialize_option<V: de::Visitor<'de>>(self, v: V) -> StdResult<V::Value, Self::Error> {
+            v.visit_none()
+        }
+
+        fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+            self,
+            _: &'static str,
+            v: V,
+        ) -> StdResult<V::Value, Self::Error> {
+            v.visit_newtype_struct(self)
+        }
+
+        fn deserialize_seq<V: de::Visitor<'de>>(self, v: V) -> StdResult<V::Value, Self::Error> {
+            v.visit_seq(de::value::SeqDeserializer::new(std::iter::empty::<Probe>()))
+        }
+
+        fn deserialize_tuple<V: de::Visitor<'de>>(
+            self,
+            len: usize,
+            v: V,
+        ) -> StdResult<V::Value, Self::Error> {
+            v.visit_seq(de::value::SeqDeserializer::new(std::iter::repeat_n(
+                self, len,
+            )))
+        }
+
+        fn deserialize_tuple_struct<V: de::Visitor<'de>>(
+            self,
+            _: &'static str,
+            len: usize,
+            v: V,
+        ) -> StdResult<V::Value, Self::Error> {
+            self.deserialize_tuple(len, v)
+        }
+
+        fn deserialize_map<V: de::Visitor<'de>>(self, v: V) -> StdResult<V::Value, Self::Error> {
+            v.visit_map(de::value::MapDeserializer::new(std::iter::empty::<(
+                Probe,
+                Probe,
+            )>()))
+        }
+
+        // nested values get every field so only the outer struct reports missing ones
+        fn deserialize_struct<V: de::Visitor<'de>>(
+            self,
+            _: &'static str,
+            fields: &'static [&'static str],
+            v: V,
+        ) -> StdResult<V::Value, Self::Error> {
+            v.visit_map(de::value::MapDeserializer::new(
+                fields.iter().map(|e| (*e, self)),
+            ))
+        }
+
+        fn deserialize_enum<V: de::Visitor<'de>>(
+            self,
+            _: &'static str,
+            variants: &'static [&'static str],
+            v: V,
+        ) -> StdResult<V::Value, Self::Error> {
+            let variant = variants
+                .first()
+                .ok_or_else(|| ProbeError::Other("enum without variants".to_string()))?;
+            v.visit_enum(ProbeVariant(variant, self))
+        }
+
+        serde::forward_to_deserialize_any! {
+            i128 u128 unit unit_struct identifier ignored_any
+        }
+    }
+
+    // enums are built from their first variant
+    struct ProbeVariant(&'static str, Probe);
+
+    impl<'de> de::EnumAccess<'de> for ProbeVariant {
+        type Error = ProbeError;
+        type Variant = Probe;
+
+        fn variant_seed<S: de::DeserializeSeed<'de>>(
+            self,
+            seed: S,
+        ) -> StdResult<(S::Value, Self::Variant), Self::Error> {
+            let variant = seed.deserialize(de::IntoDeserializer::into_deserializer(self.0))?;
+            Ok((variant, self.1))
+        }
+    }
+
+    impl<'de> de::VariantAccess<'de> for Probe {
+        type Error = ProbeError;
+
+        fn unit_variant(self) -> StdResult<(), Self::Error> {
+            Ok(())
+        }
+
+        fn newtype_variant_seed<S: de::DeserializeSeed<'de>>(
+            self,
+            seed: S,
+        ) -> StdResult<S::Value, Self::Error> {
+            seed.deserialize(self)
+        }
+
+        fn tuple_variant<V: de::Visitor<'de>>(
+            self,
+            len: usize,
+            v: V,
+        ) -> StdResult<V::Value, Self::Error> {
+            de::Deserializer::deserialize_tuple(self, len, v)
+        }
+
+        fn struct_variant<V: de::Visitor<'de>>(
+            self,
+            fields: &'static [&'static str],
+            v: V,
+        ) -> StdResult<V::Value, Self::Error> {
+            de::Deserializer::deserialize_struct(self, "", fields, v)
+        }
+    }
+
+    // the outer map, it ties a value that cannot be built to its field
+    struct ProbeFields<'a> {
+        fields: &'a [(&'static str, usize)],
+        index: usize,
+    }
+
+    impl<'de> de::MapAccess<'de> for ProbeFields<'_> {
+        type Error = ProbeError;
+
+        fn next_key_seed<K: de::DeserializeSeed<'de>>(
+            &mut self,
+            seed: K,
+        ) -> StdResult<Option<K::Value>, Self::Error> {
+            let Some((field, _)) = self.fields.get(self.index) else {
+                return Ok(None);
+            };
+            seed.deserialize(de::IntoDeserializer::into_deserializer(*field))
+                .map(Some)
+        }
+
+        fn next_value_seed<V: de::DeserializeSeed<'de>>(
+            &mut self,
+            seed: V,
+        ) -> StdResult<V::Value, Self::Error> {
+            let (field, placeholder) = self.fields[self.index];
+            self.index += 1;
+            seed.deserialize(Probe(PLACEHOLDERS[placeholder]))
+                .map_err(|_| ProbeError::InvalidField(field))
+        }
+    }
+
+    // the required fields and the placeholder each one takes
+    let mut fields: Vec<(&'static str, usize)> = Vec::new();
+
+    // serde reports one missing field at a time, keep feeding it the ones found so far
+    loop {
+        let probe = ProbeFields {
+            fields: &fields,
+            index: 0,
+        };
+
+        match T::deserialize(de::value::MapAccessDeserializer::new(probe)) {
+            Ok(_) => break,
+            Err(ProbeError::MissingField(field)) if !fields.iter().any(|e| e.0 == field) => {
+                fields.push((field, 0));
+            }
+            Err(ProbeError::InvalidField(field)) => {
+                let Some(entry) = fields.iter_mut().find(|e| e.0 == field) else {
+                    break;
+                };
+                entry.1 += 1;
+
+                if entry.1 == PLACEHOLDERS.len() {
+                    return Err(InvalidOperationError(format!(
+                        "Cannot check the csv columns, field {} cannot be probed",
+                        field
+                    ))
+                    .into());
+                }
+            }
+            // not a struct with named fields, there are no columns to check
+            Err(_) if fields.is_empty() => break,
+            Err(e) => {
+                return Err(
+                    InvalidOperationError(format!("Cannot check the csv columns. {}", e)).into(),
+                )
+            }
+        }
+    }
+
+    Ok(fields.into_iter().map(|e| e.0).collect())
 }
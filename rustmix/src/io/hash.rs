@@ -0,0 +1,263 @@
+use md5::Md5;
+#[cfg(feature = "threading")]
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use super::path::{self, EntryKind, WalkOptions};
+use crate::Result;
+
+const BUFFER_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Blake3,
+    Md5,
+    Crc32,
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HashAlgorithm::Sha256 => write!(f, "SHA-256"),
+            HashAlgorithm::Blake3 => write!(f, "BLAKE3"),
+            HashAlgorithm::Md5 => write!(f, "MD5"),
+            HashAlgorithm::Crc32 => write!(f, "CRC32"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    pub size: u64,
+    pub hash: String,
+    pub paths: Vec<PathBuf>,
+}
+
+impl DuplicateGroup {
+    pub fn original(&self) -> &Path {
+        &self.paths[0]
+    }
+
+    // every copy but the first, ready for path::del
+    pub fn redundant(&self) -> &[PathBuf] {
+        &self.paths[1..]
+    }
+
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.paths.len() as u64 - 1)
+    }
+}
+
+pub fn hash_reader<R: Read>(reader: R, algorithm: HashAlgorithm) -> Result<String> {
+    compute(reader, algorithm).map_err(Into::into)
+}
+
+pub fn hash_bytes<T: AsRef<[u8]>>(data: T, algorithm: HashAlgorithm) -> String {
+    // reading from a slice never fails
+    compute(data.as_ref(), algorithm).unwrap()
+}
+
+pub fn hash_file<T: AsRef<Path>>(path: T, algorithm: HashAlgorithm) -> Result<String> {
+    compute_file(path.as_ref(), algorithm).map_err(Into::into)
+}
+
+pub fn hash_files<T: AsRef<Path>>(
+    paths: &[T],
+    algorithm: HashAlgorithm,
+) -> Vec<(PathBuf, Result<String>)> {
+    let paths = paths
+        .iter()
+        .map(|e| e.as_ref().to_path_buf())
+        .collect::<Vec<_>>();
+    compute_files(paths, algorithm)
+        .into_iter()
+        .map(|(path, hash)| (path, hash.map_err(Into::into)))
+        .collect()
+}
+
+pub fn hash_match<T: AsRef<str>>(
+    pattern: T,
+    algorithm: HashAlgorithm,
+) -> Result<Vec<(PathBuf, Result<String>)>> {
+    let paths = path::lst_match(pattern)?
+        .filter(|e| e.is_file())
+        .collect::<Vec<_>>();
+    Ok(hash_files(&paths, algorithm))
+}
+
+pub fn find_duplicates<T: AsRef<Path>>(
+    roots: &[T],
+    options: &WalkOptions,
+    algorithm: HashAlgorithm,
+) -> Result<Vec<DuplicateGroup>> {
+    let options = options.with_kind(EntryKind::Files);
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    let mut seen: HashSet<FileKey> = HashSet::new();
+
+    for root in roots {
+        for entry in path::walk_with(root, &options)? {
+            // like the files that fail to hash below, unreadable entries are left out of the scan
+            let Ok(entry) = entry else {
+                continue;
+            };
+
+            if !entry.is_file() {
+                continue;
+            }
+
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            let size = metadata.len();
+
+            // empty files are all identical, nothing to gain from them
+            if size == 0 {
+                continue;
+            }
+
+            // overlapping roots, hard links and followed links all lead to the same file,
+            // which would otherwise be reported as its own duplicate
+            if !seen.insert(file_key(&entry.path, &metadata)) {
+                continue;
+            }
+
+            by_size.entry(size).or_default().push(entry.path);
+        }
+    }
+
+    let candidates = by_size
+        .iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .flat_map(|(size, paths)| paths.iter().map(move |e| (*size, e.clone())))
+        .collect::<Vec<_>>();
+    let sizes = candidates.iter().map(|e| e.0).collect::<Vec<_>>();
+    let hashes = compute_files(candidates.into_iter().map(|e| e.1).collect(), algorithm);
+    let mut by_hash: HashMap<(u64, String), Vec<PathBuf>> = HashMap::new();
+
+    for (size, (path, hash)) in sizes.into_iter().zip(hashes) {
+        // a file that went away or can't be read is not a duplicate of anything
+        let Ok(hash) = hash else {
+            continue;
+        };
+        by_hash.entry((size, hash)).or_default().push(path);
+    }
+
+    let mut groups = by_hash
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|((size, hash), mut paths)| {
+            paths.sort();
+            DuplicateGroup { size, hash, paths }
+        })
+        .collect::<Vec<_>>();
+    groups.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.paths.cmp(&b.paths)));
+    Ok(groups)
+}
+
+#[cfg(unix)]
+type FileKey = (u64, u64);
+
+#[cfg(unix)]
+fn file_key(_path: &Path, metadata: &fs::Metadata) -> FileKey {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.dev(), metadata.ino())
+}
+
+#[cfg(not(unix))]
+type FileKey = PathBuf;
+
+#[cfg(not(unix))]
+fn file_key(path: &Path, _metadata: &fs::Metadata) -> FileKey {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(feature = "threading")]
+fn compute_files(
+    paths: Vec<PathBuf>,
+    algorithm: HashAlgorithm,
+) -> Vec<(PathBuf, io::Result<String>)> {
+    paths
+        .into_par_iter()
+        .map(|e| {
+            let hash = compute_file(&e, algorithm);
+            (e, hash)
+        })
+        .collect()
+}
+
+#[cfg(not(feature = "threading"))]
+fn compute_files(
+    paths: Vec<PathBuf>,
+    algorithm: HashAlgorithm,
+) -> Vec<(PathBuf, io::Result<String>)> {
+    paths
+        .into_iter()
+        .map(|e| {
+            let hash = compute_file(&e, algorithm);
+            (e, hash)
+        })
+        .collect()
+}
+
+fn compute_file(path: &Path, algorithm: HashAlgorithm) -> io::Result<String> {
+    let file = File::open(path)?;
+    compute(file, algorithm)
+}
+
+fn compute<R: Read>(mut reader: R, algorithm: HashAlgorithm) -> io::Result<String> {
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+
+    match algorithm {
+        HashAlgorithm::Sha256 => {
+            let mut hasher = Sha256::new();
+            read_chunks(&mut reader, &mut buffer, |e| hasher.update(e))?;
+            Ok(to_hex(&hasher.finalize()))
+        }
+        HashAlgorithm::Blake3 => {
+            let mut hasher = blake3::Hasher::new();
+            read_chunks(&mut reader, &mut buffer, |e| {
+                hasher.update(e);
+            })?;
+            Ok(hasher.finalize().to_hex().to_string())
+        }
+        HashAlgorithm::Md5 => {
+            let mut hasher = Md5::new();
+            read_chunks(&mut reader, &mut buffer, |e| hasher.update(e))?;
+            Ok(to_hex(&hasher.finalize()))
+        }
+        HashAlgorithm::Crc32 => {
+            let mut hasher = crc32fast::Hasher::new();
+            read_chunks(&mut reader, &mut buffer, |e| hasher.update(e))?;
+            Ok(format!("{:08x}", hasher.finalize()))
+        }
+    }
+}
+
+fn read_chunks<R: Read, F: FnMut(&[u8])>(
+    reader: &mut R,
+    buffer: &mut [u8],
+    mut update: F,
+) -> io::Result<()> {
+    loop {
+        let n = match reader.read(buffer) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        update(&buffer[..n]);
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|e| format!("{:02x}", e)).collect()
+}
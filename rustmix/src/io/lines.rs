@@ -0,0 +1,341 @@
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+#[cfg(feature = "mmap")]
+use std::{fs::File, path::Path};
+
+use crate::Result;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const INDEX_STEP_DEFAULT: usize = 1000;
+
+#[derive(Debug)]
+pub struct ReverseLines<R: Read + Seek> {
+    reader: R,
+    position: u64,
+    pending: Vec<u8>,
+    unscanned: usize,
+    done: bool,
+}
+
+impl<R: Read + Seek> ReverseLines<R> {
+    pub fn new(reader: R) -> Result<Self> {
+        let mut reader = reader;
+        let size = reader.seek(SeekFrom::End(0))?;
+        let mut position = size;
+
+        // the last line terminator does not start another line
+        if position > 0 {
+            let mut last = [0u8; 1];
+            reader.seek(SeekFrom::Start(position - 1))?;
+            reader.read_exact(&mut last)?;
+
+            if last[0] == b'\n' {
+                position -= 1;
+            }
+        }
+
+        Ok(Self {
+            reader,
+            done: size == 0,
+            position,
+            pending: Vec::new(),
+            unscanned: 0,
+        })
+    }
+
+    fn read_chunk(&mut self) -> io::Result<()> {
+        let size = CHUNK_SIZE.min(self.position as usize);
+        self.position -= size as u64;
+        let mut chunk = vec![0u8; size];
+        self.reader.seek(SeekFrom::Start(self.position))?;
+        self.reader.read_exact(&mut chunk)?;
+        chunk.extend_from_slice(&self.pending);
+        self.pending = chunk;
+        // only the new bytes can hold a line break
+        self.unscanned = size;
+        Ok(())
+    }
+
+    fn next_line(&mut self) -> io::Result<Option<String>> {
+        loop {
+            if let Some(i) = self.pending[..self.unscanned]
+                .iter()
+                .rposition(|e| *e == b'\n')
+            {
+                let line = self.pending.split_off(i + 1);
+                self.pending.truncate(i);
+                self.unscanned = i;
+                return Ok(Some(to_line(&line)));
+            }
+
+            if self.position == 0 {
+                self.done = true;
+                let line = std::mem::take(&mut self.pending);
+                return Ok(Some(to_line(&line)));
+            }
+
+            self.read_chunk()?;
+        }
+    }
+}
+
+impl<R: Read + Seek> Iterator for ReverseLines<R> {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.next_line() {
+            Ok(line) => line.map(Ok),
+            Err(e) => {
+                self.done = true;
+                Some(Err(e.into()))
+            }
+        }
+    }
+}
+
+pub fn read_rev<R: Read + Seek>(reader: R) -> Result<ReverseLines<R>> {
+    ReverseLines::new(reader)
+}
+
+pub fn read_last<R: Read + Seek>(reader: R, count: usize) -> Result<Vec<String>> {
+    let mut lines = ReverseLines::new(reader)?
+        .take(count)
+        .collect::<Result<Vec<_>>>()?;
+    lines.reverse();
+    Ok(lines)
+}
+
+// Keeps the offset of every step-th line, lines are zero based
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    step: usize,
+    offsets: Vec<u64>,
+    lines: usize,
+    size: u64,
+    terminated: bool,
+}
+
+impl LineIndex {
+    pub fn build<R: Read>(reader: R, step: usize) -> Result<Self> {
+        let mut index = Self::empty(step);
+        let mut reader = BufReader::with_capacity(CHUNK_SIZE, reader);
+
+        loop {
+            let buffer = reader.fill_buf()?;
+
+            if buffer.is_empty() {
+                break;
+            }
+
+            let n = buffer.len();
+            index.scan(buffer);
+            reader.consume(n);
+        }
+
+        index.finish();
+        Ok(index)
+    }
+
+    pub fn from_bytes(data: &[u8], step: usize) -> Self {
+        let mut index = Self::empty(step);
+        index.scan(data);
+        index.finish();
+        index
+    }
+
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines == 0
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    // the closest indexed line at or before the given line and its offset
+    pub fn checkpoint(&self, line: usize) -> Option<(usize, u64)> {
+        if line >= self.lines {
+            return None;
+        }
+
+        let i = (line / self.step).min(self.offsets.len() - 1);
+        Some((i * self.step, self.offsets[i]))
+    }
+
+    pub fn seek<R: BufRead + Seek>(&self, reader: &mut R, line: usize) -> Result<bool> {
+        let Some((first, offset)) = self.checkpoint(line) else {
+            return Ok(false);
+        };
+        reader.seek(SeekFrom::Start(offset))?;
+
+        for _ in first..line {
+            reader.skip_until(b'\n')?;
+        }
+
+        Ok(true)
+    }
+
+    pub fn read_line<R: Read + Seek>(&self, reader: R, line: usize) -> Result<Option<String>> {
+        Ok(self.read_lines(reader, line, 1)?.pop())
+    }
+
+    pub fn read_lines<R: Read + Seek>(
+        &self,
+        reader: R,
+        start: usize,
+        count: usize,
+    ) -> Result<Vec<String>> {
+        let mut reader = BufReader::new(reader);
+
+        if !self.seek(&mut reader, start)? {
+            return Ok(Vec::new());
+        }
+
+        let count = count.min(self.lines - start);
+        let mut lines = Vec::with_capacity(count);
+        let mut buffer = Vec::new();
+
+        for _ in 0..count {
+            buffer.clear();
+
+            if reader.read_until(b'\n', &mut buffer)? == 0 {
+                break;
+            }
+
+            lines.push(to_line(&buffer));
+        }
+
+        Ok(lines)
+    }
+
+    fn empty(step: usize) -> Self {
+        Self {
+            step: if step == 0 { INDEX_STEP_DEFAULT } else { step },
+            offsets: vec![0],
+            lines: 0,
+            size: 0,
+            terminated: false,
+        }
+    }
+
+    fn scan(&mut self, data: &[u8]) {
+        for (i, b) in data.iter().enumerate() {
+            if *b != b'\n' {
+                continue;
+            }
+
+            self.lines += 1;
+
+            if self.lines.is_multiple_of(self.step) {
+                self.offsets.push(self.size + i as u64 + 1);
+            }
+        }
+
+        self.size += data.len() as u64;
+
+        if let Some(last) = data.last() {
+            self.terminated = *last == b'\n';
+        }
+    }
+
+    fn finish(&mut self) {
+        // an unterminated last line still counts, a trailing line break does not start one
+        if self.size > 0 && !self.terminated {
+            self.lines += 1;
+        }
+
+        if self.offsets.len() > 1 && self.offsets.last() == Some(&self.size) {
+            self.offsets.pop();
+        }
+    }
+}
+
+#[cfg(feature = "mmap")]
+#[derive(Debug)]
+pub struct MappedLines {
+    map: Mmap,
+    index: LineIndex,
+}
+
+#[cfg(feature = "mmap")]
+impl MappedLines {
+    /// # Safety
+    ///
+    /// See [`MappedLines::from_file`].
+    pub unsafe fn open<T: AsRef<Path>>(path: T, step: usize) -> Result<Self> {
+        let file = File::open(path)?;
+        Self::from_file(&file, step)
+    }
+
+    /// # Safety
+    ///
+    /// The file must not be truncated or modified in place while the map is alive,
+    /// by this or any other process. Shrinking it raises SIGBUS on access.
+    pub unsafe fn from_file(file: &File, step: usize) -> Result<Self> {
+        let map = Mmap::map(file)?;
+        let index = LineIndex::from_bytes(&map, step);
+        Ok(Self { map, index })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    pub fn index(&self) -> &LineIndex {
+        &self.index
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn line(&self, line: usize) -> Option<String> {
+        self.lines(line, 1).pop()
+    }
+
+    pub fn lines(&self, start: usize, count: usize) -> Vec<String> {
+        let Some((first, offset)) = self.index.checkpoint(start) else {
+            return Vec::new();
+        };
+        self.map[offset as usize..]
+            .split(|e| *e == b'\n')
+            .skip(start - first)
+            .take(count.min(self.index.len() - start))
+            .map(to_line)
+            .collect()
+    }
+
+    pub fn rev(&self) -> impl Iterator<Item = String> + '_ {
+        let data = self.map.strip_suffix(b"\n").unwrap_or(&self.map);
+        data.rsplit(|e| *e == b'\n')
+            .take(self.index.len())
+            .map(to_line)
+    }
+
+    pub fn tail(&self, count: usize) -> Vec<String> {
+        let count = count.min(self.index.len());
+        self.lines(self.index.len() - count, count)
+    }
+}
+
+fn to_line(line: &[u8]) -> String {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    String::from_utf8_lossy(line).into_owned()
+}
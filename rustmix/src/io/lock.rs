@@ -0,0 +1,199 @@
+use std::{
+    fmt,
+    fs::{File, OpenOptions, TryLockError},
+    io::{Read, Seek, SeekFrom, Write},
+    ops::Deref,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{directory, file};
+use crate::{
+    error::{AlreadyRunningError, TimedoutError},
+    Result,
+};
+
+const POLL_INTERVAL_MIN: Duration = Duration::from_millis(5);
+const POLL_INTERVAL_MAX: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LockMode {
+    Shared,
+    #[default]
+    Exclusive,
+}
+
+impl fmt::Display for LockMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LockMode::Shared => write!(f, "Shared"),
+            LockMode::Exclusive => write!(f, "Exclusive"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FileLock<'a> {
+    file: &'a File,
+    mode: LockMode,
+}
+
+impl FileLock<'_> {
+    pub fn mode(&self) -> LockMode {
+        self.mode
+    }
+
+    pub fn unlock(self) -> Result<()> {
+        // drop would unlock again
+        let file = self.file;
+        std::mem::forget(self);
+        file.unlock().map_err(Into::into)
+    }
+}
+
+impl Deref for FileLock<'_> {
+    type Target = File;
+
+    fn deref(&self) -> &Self::Target {
+        self.file
+    }
+}
+
+impl Drop for FileLock<'_> {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+pub fn lock(file: &File, mode: LockMode) -> Result<FileLock<'_>> {
+    match mode {
+        LockMode::Shared => file.lock_shared()?,
+        LockMode::Exclusive => file.lock()?,
+    }
+
+    Ok(FileLock { file, mode })
+}
+
+pub fn try_lock(file: &File, mode: LockMode) -> Result<Option<FileLock<'_>>> {
+    let locked = match mode {
+        LockMode::Shared => file.try_lock_shared(),
+        LockMode::Exclusive => file.try_lock(),
+    };
+
+    match locked {
+        Ok(_) => Ok(Some(FileLock { file, mode })),
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+pub fn lock_timeout(file: &File, mode: LockMode, timeout: Duration) -> Result<FileLock<'_>> {
+    let start = Instant::now();
+    let mut interval = POLL_INTERVAL_MIN;
+
+    loop {
+        if let Some(lock) = try_lock(file, mode)? {
+            return Ok(lock);
+        }
+
+        let elapsed = start.elapsed();
+
+        if elapsed >= timeout {
+            return Err(TimedoutError.into());
+        }
+
+        thread::sleep(interval.min(timeout - elapsed));
+        interval = (interval * 2).min(POLL_INTERVAL_MAX);
+    }
+}
+
+#[derive(Debug)]
+pub struct InstanceGuard {
+    file: Option<File>,
+    path: PathBuf,
+    stale_pid: Option<u32>,
+}
+
+impl InstanceGuard {
+    pub fn acquire<T: AsRef<Path>>(path: T) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        if let Some(dir) = path.parent() {
+            if !dir.as_os_str().is_empty() {
+                directory::ensure(dir)?;
+            }
+        }
+
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create(true).truncate(false);
+        let mut file = file::from_options(&path, &options)?;
+
+        // the lock goes away with the process, so a PID left in an unlocked file is stale
+        match file.try_lock() {
+            Ok(_) => {}
+            Err(TryLockError::WouldBlock) => {
+                let pid = read_pid(&path).map(|e| e.to_string()).unwrap_or_default();
+                return Err(AlreadyRunningError(pid).into());
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+
+        let stale_pid = read_pid_from(&mut file);
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        write!(file, "{}", std::process::id())?;
+        file.sync_all()?;
+        Ok(Self {
+            file: Some(file),
+            path,
+            stale_pid,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn stale_pid(&self) -> Option<u32> {
+        self.stale_pid
+    }
+
+    pub fn release(mut self) -> Result<()> {
+        self.close()
+    }
+
+    fn close(&mut self) -> Result<()> {
+        let Some(file) = self.file.take() else {
+            return Ok(());
+        };
+        // the file is kept and only emptied, removing it would let a waiting process lock a file
+        // that is no longer on disk while another one creates a new one
+        file.set_len(0)?;
+        file.unlock()?;
+        Ok(())
+    }
+}
+
+impl Drop for InstanceGuard {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+pub fn single_instance<T: AsRef<str>>(name: T) -> Result<InstanceGuard> {
+    let path = std::env::temp_dir().join(format!("{}.pid", name.as_ref()));
+    InstanceGuard::acquire(path)
+}
+
+pub fn read_pid<T: AsRef<Path>>(path: T) -> Option<u32> {
+    let mut file = File::open(path).ok()?;
+    read_pid_from(&mut file)
+}
+
+fn read_pid_from(file: &mut File) -> Option<u32> {
+    let mut text = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut text).ok()?;
+    text.trim().parse().ok()
+}
@@ -1,3 +1,15 @@
+#[cfg(any(feature = "zip", feature = "tar", feature = "gzip", feature = "zstd"))]
+pub mod archive;
+pub mod async_file;
 pub mod directory;
+pub mod encoding;
 pub mod file;
+#[cfg(feature = "hash")]
+pub mod hash;
+pub mod lines;
+pub mod lock;
 pub mod path;
+pub mod tail;
+pub mod temp;
+#[cfg(feature = "watch")]
+pub mod watcher;
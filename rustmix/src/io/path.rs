@@ -5,17 +5,268 @@ use fs_extra::{
     file as fileExtra,
 };
 use glob::glob_with;
+#[cfg(feature = "gitignore")]
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    Match,
+};
 pub use std::path::{Path, PathBuf};
-use std::{fs, result::Result as StdResult};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    ffi::{OsStr, OsString},
+    fmt,
+    fs::{self, FileTimes},
+    io::{BufReader, Read, Write},
+    path::Component,
+    result::Result as StdResult,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+use crate::{
+    error::*,
+    string::*,
+    threading::{HumanBytes, Spinner},
+    CallbackHandler, Result,
+};
+
+const TRANSFER_BUFFER_DEF: usize = 64 * 1024;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SymlinkPolicy {
+    #[default]
+    Keep,
+    Follow,
+    Skip,
+}
+
+impl fmt::Display for SymlinkPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymlinkPolicy::Keep => write!(f, "Keep"),
+            SymlinkPolicy::Follow => write!(f, "Follow"),
+            SymlinkPolicy::Skip => write!(f, "Skip"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EntryKind {
+    #[default]
+    All,
+    Files,
+    Directories,
+}
+
+impl fmt::Display for EntryKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EntryKind::All => write!(f, "All"),
+            EntryKind::Files => write!(f, "Files"),
+            EntryKind::Directories => write!(f, "Directories"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalkOptions {
+    pub max_depth: Option<usize>,
+    pub symlinks: SymlinkPolicy,
+    pub include_hidden: bool,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    #[cfg(feature = "gitignore")]
+    pub git_ignore: bool,
+    #[cfg(feature = "gitignore")]
+    pub ignore_files: Vec<String>,
+    pub kind: EntryKind,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub modified_after: Option<SystemTime>,
+    pub modified_before: Option<SystemTime>,
+    pub sorted: bool,
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        WalkOptions {
+            max_depth: None,
+            symlinks: SymlinkPolicy::default(),
+            include_hidden: true,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            #[cfg(feature = "gitignore")]
+            git_ignore: false,
+            #[cfg(feature = "gitignore")]
+            ignore_files: Vec::new(),
+            kind: EntryKind::default(),
+            min_size: None,
+            max_size: None,
+            modified_after: None,
+            modified_before: None,
+            sorted: false,
+        }
+    }
+}
+
+impl WalkOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_max_depth(&self, max_depth: usize) -> Self {
+        WalkOptions {
+            max_depth: Some(max_depth),
+            ..self.clone()
+        }
+    }
+
+    pub fn with_symlinks(&self, symlinks: SymlinkPolicy) -> Self {
+        WalkOptions {
+            symlinks,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_hidden(&self, include_hidden: bool) -> Self {
+        WalkOptions {
+            include_hidden,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_include<T: AsRef<str>>(&self, patterns: &[T]) -> Self {
+        WalkOptions {
+            include: patterns.iter().map(|e| e.as_ref().to_string()).collect(),
+            ..self.clone()
+        }
+    }
+
+    pub fn with_exclude<T: AsRef<str>>(&self, patterns: &[T]) -> Self {
+        WalkOptions {
+            exclude: patterns.iter().map(|e| e.as_ref().to_string()).collect(),
+            ..self.clone()
+        }
+    }
+
+    #[cfg(feature = "gitignore")]
+    pub fn with_git_ignore(&self, git_ignore: bool) -> Self {
+        WalkOptions {
+            git_ignore,
+            ..self.clone()
+        }
+    }
+
+    #[cfg(feature = "gitignore")]
+    pub fn with_ignore_files<T: AsRef<str>>(&self, names: &[T]) -> Self {
+        WalkOptions {
+            ignore_files: names.iter().map(|e| e.as_ref().to_string()).collect(),
+            ..self.clone()
+        }
+    }
+
+    pub fn with_kind(&self, kind: EntryKind) -> Self {
+        WalkOptions {
+            kind,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_size(&self, min_size: Option<u64>, max_size: Option<u64>) -> Self {
+        WalkOptions {
+            min_size,
+            max_size,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_modified(
+        &self,
+        modified_after: Option<SystemTime>,
+        modified_before: Option<SystemTime>,
+    ) -> Self {
+        WalkOptions {
+            modified_after,
+            modified_before,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_sorted(&self, sorted: bool) -> Self {
+        WalkOptions {
+            sorted,
+            ..self.clone()
+        }
+    }
+
+    fn has_metadata_filters(&self) -> bool {
+        self.min_size.is_some()
+            || self.max_size.is_some()
+            || self.modified_after.is_some()
+            || self.modified_before.is_some()
+    }
+
+    fn matches_metadata(&self, metadata: &fs::Metadata) -> bool {
+        let size = metadata.len();
+
+        if self.min_size.is_some_and(|e| size < e) || self.max_size.is_some_and(|e| size > e) {
+            return false;
+        }
+
+        if self.modified_after.is_none() && self.modified_before.is_none() {
+            return true;
+        }
+
+        let Ok(modified) = metadata.modified() else {
+            return false;
+        };
+
+        !(self.modified_after.is_some_and(|e| modified < e)
+            || self.modified_before.is_some_and(|e| modified > e))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WalkEntry {
+    pub path: PathBuf,
+    pub depth: usize,
+    pub file_type: Option<fs::FileType>,
+    pub is_symlink: bool,
+}
+
+impl WalkEntry {
+    pub fn is_file(&self) -> bool {
+        self.file_type.is_some_and(|e| e.is_file())
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type.is_some_and(|e| e.is_dir())
+    }
+
+    pub fn metadata(&self) -> Result<fs::Metadata> {
+        if self.is_symlink && !self.is_dir() && !self.is_file() {
+            return fs::symlink_metadata(&self.path).map_err(Into::into);
+        }
 
-use crate::{error::*, string::*, Result};
+        fs::metadata(&self.path).map_err(Into::into)
+    }
+}
 
 pub trait PathEx {
     fn as_str(&self) -> &str;
+    fn try_as_str(&self) -> Result<&str>;
+    fn try_full_path(&self) -> Result<PathBuf>;
     fn exists(&self) -> bool;
     fn is_empty(&self) -> bool;
     fn take(&self, n: usize) -> PathBuf;
     fn remove(&self, n: usize) -> PathBuf;
+    fn lexical_normalize(&self) -> PathBuf;
+    fn relative_to<B: AsRef<Path>>(&self, base: B) -> Result<PathBuf>;
+    fn expand(&self) -> Result<PathBuf>;
+    fn safe_join<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf>;
 }
 
 impl<T: AsRef<Path>> PathEx for T {
@@ -23,6 +274,14 @@ impl<T: AsRef<Path>> PathEx for T {
         self.as_ref().to_str().unwrap_or_default()
     }
 
+    fn try_as_str(&self) -> Result<&str> {
+        path_to_str(self.as_ref())
+    }
+
+    fn try_full_path(&self) -> Result<PathBuf> {
+        full_path(self.as_ref())
+    }
+
     fn exists(&self) -> bool {
         self.as_ref().exists()
     }
@@ -51,6 +310,148 @@ impl<T: AsRef<Path>> PathEx for T {
 
         path
     }
+
+    fn lexical_normalize(&self) -> PathBuf {
+        let mut path = PathBuf::new();
+        // number of normal components that a '..' can still remove
+        let mut depth = 0usize;
+
+        for component in self.as_ref().components() {
+            match component {
+                Component::Prefix(_) | Component::RootDir => path.push(component),
+                Component::CurDir => {}
+                Component::ParentDir if depth > 0 => {
+                    path.pop();
+                    depth -= 1;
+                }
+                // '..' at the root is the root itself
+                Component::ParentDir if path.has_root() => {}
+                Component::ParentDir => path.push(component),
+                Component::Normal(_) => {
+                    path.push(component);
+                    depth += 1;
+                }
+            }
+        }
+
+        if path.as_os_str().is_empty() {
+            path.push(Component::CurDir);
+        }
+
+        path
+    }
+
+    fn relative_to<B: AsRef<Path>>(&self, base: B) -> Result<PathBuf> {
+        let path = self.lexical_normalize();
+        let base = base.as_ref().lexical_normalize();
+
+        if path.has_root() != base.has_root() {
+            return Err(InvalidOperationError(format!(
+                "Cannot compute a relative path between '{}' and '{}'",
+                path.display(),
+                base.display()
+            ))
+            .into());
+        }
+
+        let path_parts = path
+            .components()
+            .filter(|e| *e != Component::CurDir)
+            .collect::<Vec<_>>();
+        let base_parts = base
+            .components()
+            .filter(|e| *e != Component::CurDir)
+            .collect::<Vec<_>>();
+        let common = path_parts
+            .iter()
+            .zip(base_parts.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        // different drives on Windows have nothing in common
+        if path.has_root() && common == 0 {
+            return Err(InvalidOperationError(format!(
+                "Paths have different roots '{}' and '{}'",
+                path.display(),
+                base.display()
+            ))
+            .into());
+        }
+
+        // a base that starts with '..' beyond the common part can't be walked back
+        if base_parts[common..].contains(&Component::ParentDir) {
+            return Err(InvalidOperationError(format!(
+                "Cannot compute a relative path from '{}'",
+                base.display()
+            ))
+            .into());
+        }
+
+        let mut relative = PathBuf::new();
+
+        for _ in common..base_parts.len() {
+            relative.push(Component::ParentDir);
+        }
+
+        for component in &path_parts[common..] {
+            relative.push(component);
+        }
+
+        if relative.as_os_str().is_empty() {
+            relative.push(Component::CurDir);
+        }
+
+        Ok(relative)
+    }
+
+    fn expand(&self) -> Result<PathBuf> {
+        let path = path_to_str(self.as_ref())?;
+        let path = expand_vars(path)?;
+        let mut chars = path.chars();
+
+        if chars.next() != Some('~') {
+            return Ok(PathBuf::from(path));
+        }
+
+        let rest = chars.as_str();
+
+        // ~user is not supported, only the current user's home
+        if !rest.is_empty() && !rest.starts_with(std::path::is_separator) {
+            return Ok(PathBuf::from(path));
+        }
+
+        let home = home_dir().ok_or_else(|| NotFoundError("Home directory".to_string()))?;
+        let rest = rest.trim_start_matches(std::path::is_separator);
+
+        if rest.is_empty() {
+            return Ok(home);
+        }
+
+        Ok(home.join(rest))
+    }
+
+    fn safe_join<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        let name = path.as_ref();
+        let mut path = self.as_ref().to_path_buf();
+        let mut depth = 0usize;
+
+        for component in name.components() {
+            match component {
+                Component::Normal(e) => {
+                    path.push(e);
+                    depth += 1;
+                }
+                Component::CurDir => {}
+                Component::ParentDir if depth > 0 => {
+                    path.pop();
+                    depth -= 1;
+                }
+                _ => return Err(UnsafePathError(name.to_string_lossy().into_owned()).into()),
+            }
+        }
+
+        Ok(path)
+    }
 }
 
 pub trait IntoPath<T> {
@@ -140,6 +541,8 @@ impl<T: AsRef<str>> IntoPath<T> for Vec<T> {
 pub trait AsPath<T> {
     fn as_path(&self) -> String;
     fn as_full_path(&self) -> String;
+    fn try_as_path(&self) -> Result<String>;
+    fn try_as_full_path(&self) -> Result<String>;
 }
 
 impl<T: AsRef<str>> AsPath<T> for (T, T) {
@@ -152,6 +555,16 @@ impl<T: AsRef<str>> AsPath<T> for (T, T) {
         let path = self.into_path();
         path.canonicalize().unwrap().as_str().to_string()
     }
+
+    fn try_as_path(&self) -> Result<String> {
+        let path = self.into_path();
+        path_to_str(&path).map(str::to_string)
+    }
+
+    fn try_as_full_path(&self) -> Result<String> {
+        let path = full_path(&self.into_path())?;
+        path_to_str(&path).map(str::to_string)
+    }
 }
 
 impl<T: AsRef<str>> AsPath<T> for (T, T, T) {
@@ -164,6 +577,16 @@ impl<T: AsRef<str>> AsPath<T> for (T, T, T) {
         let path = self.into_path();
         path.canonicalize().unwrap().as_str().to_string()
     }
+
+    fn try_as_path(&self) -> Result<String> {
+        let path = self.into_path();
+        path_to_str(&path).map(str::to_string)
+    }
+
+    fn try_as_full_path(&self) -> Result<String> {
+        let path = full_path(&self.into_path())?;
+        path_to_str(&path).map(str::to_string)
+    }
 }
 
 impl<T: AsRef<str>> AsPath<T> for (T, T, T, T) {
@@ -176,6 +599,16 @@ impl<T: AsRef<str>> AsPath<T> for (T, T, T, T) {
         let path = self.into_path();
         path.canonicalize().unwrap().as_str().to_string()
     }
+
+    fn try_as_path(&self) -> Result<String> {
+        let path = self.into_path();
+        path_to_str(&path).map(str::to_string)
+    }
+
+    fn try_as_full_path(&self) -> Result<String> {
+        let path = full_path(&self.into_path())?;
+        path_to_str(&path).map(str::to_string)
+    }
 }
 
 impl<T: AsRef<str>> AsPath<T> for (T, T, T, T, T) {
@@ -188,6 +621,16 @@ impl<T: AsRef<str>> AsPath<T> for (T, T, T, T, T) {
         let path = self.into_path();
         path.canonicalize().unwrap().as_str().to_string()
     }
+
+    fn try_as_path(&self) -> Result<String> {
+        let path = self.into_path();
+        path_to_str(&path).map(str::to_string)
+    }
+
+    fn try_as_full_path(&self) -> Result<String> {
+        let path = full_path(&self.into_path())?;
+        path_to_str(&path).map(str::to_string)
+    }
 }
 
 impl<T: AsRef<str>, const N: usize> AsPath<T> for [T; N] {
@@ -200,6 +643,16 @@ impl<T: AsRef<str>, const N: usize> AsPath<T> for [T; N] {
         let path = self.into_path();
         path.canonicalize().unwrap().as_str().to_string()
     }
+
+    fn try_as_path(&self) -> Result<String> {
+        let path = self.into_path();
+        path_to_str(&path).map(str::to_string)
+    }
+
+    fn try_as_full_path(&self) -> Result<String> {
+        let path = full_path(&self.into_path())?;
+        path_to_str(&path).map(str::to_string)
+    }
 }
 
 impl<T: AsRef<str>> AsPath<T> for Vec<T> {
@@ -212,6 +665,101 @@ impl<T: AsRef<str>> AsPath<T> for Vec<T> {
         let path = self.into_path();
         path.canonicalize().unwrap().as_str().to_string()
     }
+
+    fn try_as_path(&self) -> Result<String> {
+        let path = self.into_path();
+        path_to_str(&path).map(str::to_string)
+    }
+
+    fn try_as_full_path(&self) -> Result<String> {
+        let path = full_path(&self.into_path())?;
+        path_to_str(&path).map(str::to_string)
+    }
+}
+
+fn path_to_str(path: &Path) -> Result<&str> {
+    path.to_str()
+        .ok_or_else(|| InvalidUtf8PathError(path.to_string_lossy().into_owned()).into())
+}
+
+fn expand_vars(path: &str) -> Result<String> {
+    let mut expanded = String::with_capacity(path.len());
+    let mut rest = path;
+
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, next) = if let Some(braced) = after.strip_prefix('{') {
+            let Some(end) = braced.find('}') else {
+                return Err(
+                    InvalidOperationError(format!("Unclosed variable in '{}'", path)).into(),
+                );
+            };
+            (&braced[..end], &braced[end + 1..])
+        } else {
+            let end = after
+                .find(|e: char| !(e.is_ascii_alphanumeric() || e == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], &after[end..])
+        };
+
+        // a lone '$' is kept as is
+        if name.is_empty() {
+            expanded.push('$');
+            rest = after;
+            continue;
+        }
+
+        let value = std::env::var(name)
+            .map_err(|_| NotFoundError(format!("Environment variable {}", name)))?;
+        expanded.push_str(&value);
+        rest = next;
+    }
+
+    expanded.push_str(rest);
+
+    #[cfg(windows)]
+    let expanded = expand_windows_vars(&expanded)?;
+
+    Ok(expanded)
+}
+
+#[cfg(windows)]
+fn expand_windows_vars(path: &str) -> Result<String> {
+    let mut expanded = String::with_capacity(path.len());
+    let mut rest = path;
+
+    while let Some(start) = rest.find('%') {
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('%') else {
+            break;
+        };
+        expanded.push_str(&rest[..start]);
+        let name = &after[..end];
+
+        if name.is_empty() {
+            expanded.push('%');
+        } else {
+            let value = std::env::var(name)
+                .map_err(|_| NotFoundError(format!("Environment variable {}", name)))?;
+            expanded.push_str(&value);
+        }
+
+        rest = &after[end + 1..];
+    }
+
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+fn full_path(path: &Path) -> Result<PathBuf> {
+    match path.canonicalize() {
+        Ok(path) => Ok(path),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Err(NotFoundError(path.to_string_lossy().into_owned()).into())
+        }
+        Err(e) => Err(e.into()),
+    }
 }
 
 fn append_if_not_empty(path: &mut PathBuf, component: &str) {
@@ -229,7 +777,7 @@ fn file_options_to_dir_options(options: &CopyOptions) -> DirCopyOptions {
     }
 }
 
-fn glob_defaults() -> glob::MatchOptions {
+pub(crate) fn glob_defaults() -> glob::MatchOptions {
     glob::MatchOptions {
         case_sensitive: false,
         ..Default::default()
@@ -249,6 +797,15 @@ pub fn get_full_path<T: AsRef<str>>(path: T) -> String {
         .to_string()
 }
 
+pub fn try_get_full_path<T: AsRef<Path>>(path: T) -> Result<String> {
+    let path = full_path(path.as_ref())?;
+    path_to_str(&path).map(str::to_string)
+}
+
+pub fn get_full_path_os<T: AsRef<Path>>(path: T) -> Result<PathBuf> {
+    full_path(path.as_ref())
+}
+
 pub fn is_absolute<T: AsRef<str>>(path: T) -> bool {
     Path::new(path.as_ref()).is_absolute()
 }
@@ -309,6 +866,41 @@ pub fn set_extension<T: AsRef<str>>(path: T, ext: Option<&str>) -> String {
     path.to_string_lossy().into_owned()
 }
 
+pub fn split_os<T: AsRef<Path>>(path: T) -> Vec<OsString> {
+    path.as_ref()
+        .iter()
+        .filter(|e| !e.is_empty())
+        .map(OsStr::to_os_string)
+        .collect()
+}
+
+pub fn parent_os<T: AsRef<Path>>(path: T) -> Option<PathBuf> {
+    path.as_ref().parent().map(Path::to_path_buf)
+}
+
+pub fn name_os<T: AsRef<Path>>(path: T) -> Option<OsString> {
+    path.as_ref().file_name().map(OsStr::to_os_string)
+}
+
+pub fn base_name_os<T: AsRef<Path>>(path: T) -> Option<OsString> {
+    path.as_ref().file_stem().map(OsStr::to_os_string)
+}
+
+pub fn extension_os<T: AsRef<Path>>(path: T) -> Option<OsString> {
+    path.as_ref().extension().map(OsStr::to_os_string)
+}
+
+pub fn set_extension_os<T: AsRef<Path>, E: AsRef<OsStr>>(path: T, ext: Option<E>) -> PathBuf {
+    let mut path = path.as_ref().to_path_buf();
+
+    match ext {
+        Some(ext) => path.set_extension(ext),
+        None => path.set_extension(""),
+    };
+
+    path
+}
+
 pub fn lst<T: AsRef<Path>>(path: T) -> Result<impl Iterator<Item = PathBuf>> {
     let path = path.as_ref();
 
@@ -324,6 +916,23 @@ pub fn lst<T: AsRef<Path>>(path: T) -> Result<impl Iterator<Item = PathBuf>> {
     Ok(Box::new(iter) as Box<dyn Iterator<Item = _>>)
 }
 
+pub fn try_lst<T: AsRef<Path>>(path: T) -> Result<impl Iterator<Item = PathBuf>> {
+    let path = path.as_ref();
+
+    if !path.is_dir() {
+        let name = path.to_string_lossy().into_owned();
+
+        if path.exists() {
+            return Err(InvalidDirectoryError(name).into());
+        }
+
+        return Err(NotFoundError(name).into());
+    }
+
+    let read_dir = fs::read_dir(path)?;
+    Ok(read_dir.filter_map(|e| e.ok().map(|e| e.path())))
+}
+
 pub fn lst_filtered<T: AsRef<Path>, F: Fn(&PathBuf) -> bool + 'static>(
     path: T,
     filter: F,
@@ -488,54 +1097,1295 @@ pub fn mov_with<F: AsRef<str>, T: AsRef<Path>>(from: F, to: T, option: &CopyOpti
     }
 }
 
-pub fn ren<F: AsRef<Path>, T: AsRef<str>>(from: F, to: T) -> Result<()> {
-    let to = to.as_ref();
+#[derive(Debug, Clone)]
+pub struct TransferOptions {
+    pub overwrite: bool,
+    pub skip_exist: bool,
+    pub buffer_size: usize,
+    pub preserve_permissions: bool,
+    pub preserve_timestamps: bool,
+    pub cancel: Option<Arc<AtomicBool>>,
+}
 
-    if to.contains(std::path::is_separator) {
-        let to = Path::new(to);
-        return fs::rename(from, to).map_err(Into::into);
+impl Default for TransferOptions {
+    fn default() -> Self {
+        TransferOptions {
+            overwrite: false,
+            skip_exist: false,
+            buffer_size: TRANSFER_BUFFER_DEF,
+            preserve_permissions: true,
+            preserve_timestamps: false,
+            cancel: None,
+        }
     }
-
-    let from = from.as_ref();
-    let parent = from.parent().unwrap();
-    let to = parent.join(to);
-    fs::rename(from, to).map_err(Into::into)
 }
 
-pub fn del<T: AsRef<Path>>(path: T) -> Result<()> {
-    let path = path.as_ref();
-
-    if path.is_dir() {
-        fs::remove_dir_all(path).map_err(Into::into)
-    } else {
-        fs::remove_file(path).map_err(Into::into)
+impl TransferOptions {
+    pub fn new() -> Self {
+        Default::default()
     }
-}
 
-pub fn del_match<T: AsRef<Path>>(path: T, pattern: &str) -> Result<()> {
-    let path = path.as_ref();
+    pub fn with_overwrite(&self, overwrite: bool) -> Self {
+        TransferOptions {
+            overwrite,
+            ..self.clone()
+        }
+    }
 
-    if !path.is_dir() {
-        if path.is_file() {
-            return Err(InvalidDirectoryError(path.to_string_lossy().into_owned()).into());
+    pub fn with_skip_exist(&self, skip_exist: bool) -> Self {
+        TransferOptions {
+            skip_exist,
+            ..self.clone()
         }
-        return Ok(());
     }
 
-    if pattern.is_empty() {
-        return Err(InvalidOperationError("Empty source path".to_string()).into());
+    pub fn with_buffer_size(&self, buffer_size: usize) -> Self {
+        TransferOptions {
+            buffer_size: buffer_size.max(1),
+            ..self.clone()
+        }
     }
 
-    let pattern = format!("{}/{}", path.to_string_lossy(), pattern);
-    let paths = glob_with(&pattern, glob_defaults())?;
+    pub fn with_preserve(&self, permissions: bool, timestamps: bool) -> Self {
+        TransferOptions {
+            preserve_permissions: permissions,
+            preserve_timestamps: timestamps,
+            ..self.clone()
+        }
+    }
 
-    for entry in paths.filter_map(|e| e.ok()) {
-        if entry.is_dir() {
-            fs::remove_dir_all(entry)?;
-        } else {
-            fs::remove_file(entry)?;
+    pub fn with_cancel(&self, cancel: Arc<AtomicBool>) -> Self {
+        TransferOptions {
+            cancel: Some(cancel),
+            ..self.clone()
         }
     }
 
-    Ok(())
+    fn is_cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|e| e.load(Ordering::SeqCst))
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TransferProgress {
+    pub path: PathBuf,
+    pub files: u64,
+    pub total_files: u64,
+    pub bytes: u64,
+    pub total_bytes: u64,
+}
+
+impl fmt::Display for TransferProgress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} files, {}/{}",
+            self.files,
+            self.total_files,
+            HumanBytes(self.bytes),
+            HumanBytes(self.total_bytes)
+        )
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TransferSummary {
+    pub copied: Vec<PathBuf>,
+    pub skipped: Vec<PathBuf>,
+    pub failed: Vec<(PathBuf, String)>,
+    pub bytes: u64,
+    pub cancelled: bool,
+}
+
+impl TransferSummary {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty() && !self.cancelled
+    }
+}
+
+impl CallbackHandler<TransferProgress> for Spinner {
+    fn starting(&self) {
+        self.set_message("Preparing...");
+    }
+
+    fn update(&self, data: TransferProgress) {
+        self.set_message(data.to_string());
+    }
+
+    fn completed(&self) {
+        let _ = self.finish();
+    }
+}
+
+struct NoProgress;
+
+impl CallbackHandler<TransferProgress> for NoProgress {
+    fn starting(&self) {}
+    fn update(&self, _data: TransferProgress) {}
+    fn completed(&self) {}
+}
+
+pub fn cpy_progress<F: AsRef<str>, T: AsRef<Path>, H: CallbackHandler<TransferProgress>>(
+    from: F,
+    to: T,
+    options: &TransferOptions,
+    handler: &H,
+) -> Result<TransferSummary> {
+    transfer(from.as_ref(), to.as_ref(), options, handler, false)
+}
+
+pub fn mov_progress<F: AsRef<str>, T: AsRef<Path>, H: CallbackHandler<TransferProgress>>(
+    from: F,
+    to: T,
+    options: &TransferOptions,
+    handler: &H,
+) -> Result<TransferSummary> {
+    transfer(from.as_ref(), to.as_ref(), options, handler, true)
+}
+
+pub fn cpy_cancellable<F: AsRef<str>, T: AsRef<Path>>(
+    from: F,
+    to: T,
+    options: &TransferOptions,
+) -> Result<TransferSummary> {
+    cpy_progress(from, to, options, &NoProgress)
+}
+
+pub fn mov_cancellable<F: AsRef<str>, T: AsRef<Path>>(
+    from: F,
+    to: T,
+    options: &TransferOptions,
+) -> Result<TransferSummary> {
+    mov_progress(from, to, options, &NoProgress)
+}
+
+struct TransferItem {
+    from: PathBuf,
+    to: PathBuf,
+    is_dir: bool,
+    is_symlink: bool,
+    size: u64,
+}
+
+fn transfer<H: CallbackHandler<TransferProgress>>(
+    from: &str,
+    to: &Path,
+    options: &TransferOptions,
+    handler: &H,
+    remove_source: bool,
+) -> Result<TransferSummary> {
+    if from.is_empty() {
+        return Err(InvalidOperationError("Empty source path".to_string()).into());
+    }
+
+    let sources: Vec<PathBuf> = if from.find_first(|e| e == '*' || e == '?').is_some() {
+        glob_with(from, glob_defaults())?
+            .filter_map(StdResult::ok)
+            .collect()
+    } else {
+        let from = PathBuf::from(from);
+
+        if !from.exists() {
+            return Err(NotFoundError(from.to_string_lossy().into_owned()).into());
+        }
+
+        vec![from]
+    };
+
+    handler.starting();
+    fs::create_dir_all(to)?;
+
+    let mut summary = TransferSummary::default();
+    let mut items = Vec::new();
+
+    for source in &sources {
+        let Some(name) = source.file_name() else {
+            continue;
+        };
+        let destination = to.join(name);
+
+        if let Err(e) = plan_transfer(source, &destination, &mut items) {
+            summary.failed.push((source.clone(), e.to_string()));
+        }
+    }
+
+    let mut progress = TransferProgress {
+        total_files: items.iter().filter(|e| !e.is_dir).count() as u64,
+        total_bytes: items.iter().map(|e| e.size).sum(),
+        ..Default::default()
+    };
+    handler.update(progress.clone());
+
+    for item in &items {
+        if options.is_cancelled() {
+            summary.cancelled = true;
+            break;
+        }
+
+        if item.is_dir {
+            if let Err(e) = fs::create_dir_all(&item.to) {
+                summary.failed.push((item.from.clone(), e.to_string()));
+            }
+
+            continue;
+        }
+
+        progress.path = item.from.clone();
+
+        if item.to.exists() || item.to.is_symlink() {
+            if options.skip_exist {
+                summary.skipped.push(item.from.clone());
+                progress.files += 1;
+                progress.bytes += item.size;
+                handler.update(progress.clone());
+                continue;
+            }
+
+            if !options.overwrite {
+                let message = format!("Destination exists: {}", item.to.display());
+                summary.failed.push((item.from.clone(), message));
+                progress.files += 1;
+                progress.bytes += item.size;
+                handler.update(progress.clone());
+                continue;
+            }
+        }
+
+        let start_bytes = progress.bytes;
+        let result = if remove_source && fs::rename(&item.from, &item.to).is_ok() {
+            progress.bytes += item.size;
+            Ok(())
+        } else {
+            let copied = if item.is_symlink {
+                copy_link(item)
+            } else {
+                copy_file(item, options, handler, &mut progress)
+            };
+
+            copied.and_then(|_| {
+                if remove_source {
+                    fs::remove_file(&item.from)?;
+                }
+
+                Ok(())
+            })
+        };
+
+        match result {
+            Ok(_) => {
+                summary.copied.push(item.from.clone());
+                summary.bytes += item.size;
+            }
+            Err(e) if e.is::<CanceledError>() => {
+                let _ = fs::remove_file(&item.to);
+                summary.cancelled = true;
+                break;
+            }
+            Err(e) => {
+                summary.failed.push((item.from.clone(), e.to_string()));
+            }
+        }
+
+        progress.files += 1;
+        progress.bytes = start_bytes + item.size;
+        handler.update(progress.clone());
+    }
+
+    if remove_source && !summary.cancelled {
+        // files that failed or were skipped keep their directories alive
+        for item in items.iter().rev().filter(|e| e.is_dir) {
+            let _ = fs::remove_dir(&item.from);
+        }
+    }
+
+    handler.completed();
+    Ok(summary)
+}
+
+fn plan_transfer(from: &Path, to: &Path, items: &mut Vec<TransferItem>) -> Result<()> {
+    let metadata = fs::metadata(from)?;
+
+    if !metadata.is_dir() {
+        items.push(TransferItem {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+            is_dir: false,
+            is_symlink: false,
+            size: metadata.len(),
+        });
+        return Ok(());
+    }
+
+    // planned apart so a source that cannot be read completely is not transferred partially
+    let mut planned = vec![TransferItem {
+        from: from.to_path_buf(),
+        to: to.to_path_buf(),
+        is_dir: true,
+        is_symlink: false,
+        size: 0,
+    }];
+
+    for entry in walk_with(from, &WalkOptions::new().with_sorted(true))? {
+        let entry = entry?;
+        let relative = entry.path.strip_prefix(from)?;
+        let is_dir = entry.is_dir();
+        // links are recreated as links, one that points to a directory cannot be read as a file
+        let size = if is_dir || entry.is_symlink {
+            0
+        } else {
+            entry.metadata()?.len()
+        };
+        planned.push(TransferItem {
+            from: entry.path.clone(),
+            to: to.join(relative),
+            is_dir,
+            is_symlink: entry.is_symlink,
+            size,
+        });
+    }
+
+    items.append(&mut planned);
+    Ok(())
+}
+
+fn copy_link(item: &TransferItem) -> Result<()> {
+    if let Some(parent) = item.to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let target = fs::read_link(&item.from)?;
+
+    if item.to.is_symlink() || item.to.is_file() {
+        fs::remove_file(&item.to)?;
+    }
+
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(&target, &item.to)?;
+
+    #[cfg(windows)]
+    {
+        // relative targets are resolved from the directory of the link
+        let resolved = item.to.parent().unwrap_or(Path::new(".")).join(&target);
+
+        if resolved.is_dir() {
+            std::os::windows::fs::symlink_dir(&target, &item.to)?;
+        } else {
+            std::os::windows::fs::symlink_file(&target, &item.to)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn copy_file<H: CallbackHandler<TransferProgress>>(
+    item: &TransferItem,
+    options: &TransferOptions,
+    handler: &H,
+    progress: &mut TransferProgress,
+) -> Result<()> {
+    if let Some(parent) = item.to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut reader = fs::File::open(&item.from)?;
+    let mut writer = fs::File::create(&item.to)?;
+    let mut buffer = vec![0u8; options.buffer_size.max(1)];
+
+    loop {
+        if options.is_cancelled() {
+            return Err(CanceledError.into());
+        }
+
+        let n = reader.read(&mut buffer)?;
+
+        if n == 0 {
+            break;
+        }
+
+        writer.write_all(&buffer[..n])?;
+        progress.bytes += n as u64;
+        handler.update(progress.clone());
+    }
+
+    writer.flush()?;
+    let metadata = reader.metadata()?;
+
+    if options.preserve_timestamps {
+        let mut times = FileTimes::new();
+
+        if let Ok(accessed) = metadata.accessed() {
+            times = times.set_accessed(accessed);
+        }
+
+        if let Ok(modified) = metadata.modified() {
+            times = times.set_modified(modified);
+        }
+
+        writer.set_times(times)?;
+    }
+
+    if options.preserve_permissions {
+        writer.set_permissions(metadata.permissions())?;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CompareMethod {
+    #[default]
+    SizeAndTime,
+    Content,
+}
+
+impl fmt::Display for CompareMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompareMethod::SizeAndTime => write!(f, "SizeAndTime"),
+            CompareMethod::Content => write!(f, "Content"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SyncOptions {
+    pub compare: CompareMethod,
+    pub dry_run: bool,
+    pub delete_extraneous: bool,
+    pub exclude: Vec<String>,
+}
+
+impl SyncOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_compare(&self, compare: CompareMethod) -> Self {
+        SyncOptions {
+            compare,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_dry_run(&self, dry_run: bool) -> Self {
+        SyncOptions {
+            dry_run,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_delete_extraneous(&self, delete_extraneous: bool) -> Self {
+        SyncOptions {
+            delete_extraneous,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_exclude<T: AsRef<str>>(&self, patterns: &[T]) -> Self {
+        SyncOptions {
+            exclude: patterns.iter().map(|e| e.as_ref().to_string()).collect(),
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DirDiff {
+    pub added: Vec<PathBuf>,
+    pub modified: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+    // entries that could not be read or compared
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+impl DirDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.modified.is_empty() && self.removed.is_empty()
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SyncSummary {
+    pub diff: DirDiff,
+    pub copied: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+    pub failed: Vec<(PathBuf, String)>,
+    pub dry_run: bool,
+}
+
+impl SyncSummary {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+struct SyncEntry {
+    is_dir: bool,
+    is_symlink: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+pub fn diff<S: AsRef<Path>, T: AsRef<Path>>(
+    source: S,
+    target: T,
+    options: &SyncOptions,
+) -> Result<DirDiff> {
+    let source = source.as_ref();
+    let target = target.as_ref();
+
+    if !source.is_dir() {
+        return Err(InvalidDirectoryError(source.to_string_lossy().into_owned()).into());
+    }
+
+    let mut diff = DirDiff::default();
+    let source_entries = collect_sync_entries(source, options, &mut diff.failed)?;
+    // whatever could not be read in the source is not known to be missing from it
+    let unknown: Vec<PathBuf> = diff.failed.iter().map(|(e, _)| e.clone()).collect();
+    let target_entries = if target.is_dir() {
+        collect_sync_entries(target, options, &mut diff.failed)?
+    } else {
+        BTreeMap::new()
+    };
+
+    for (relative, entry) in &source_entries {
+        let Some(other) = target_entries.get(relative) else {
+            diff.added.push(relative.clone());
+            continue;
+        };
+
+        if entry.is_dir && other.is_dir {
+            continue;
+        }
+
+        if entry.is_dir != other.is_dir {
+            diff.modified.push(relative.clone());
+            continue;
+        }
+
+        match is_same_file(
+            &source.join(relative),
+            entry,
+            &target.join(relative),
+            other,
+            options.compare,
+        ) {
+            Ok(true) => {}
+            Ok(false) => diff.modified.push(relative.clone()),
+            Err(e) => diff.failed.push((relative.clone(), e.to_string())),
+        }
+    }
+
+    for relative in target_entries.keys() {
+        if !source_entries.contains_key(relative)
+            && !unknown.iter().any(|e| relative.starts_with(e))
+        {
+            diff.removed.push(relative.clone());
+        }
+    }
+
+    Ok(diff)
+}
+
+pub fn mirror<S: AsRef<Path>, T: AsRef<Path>>(
+    source: S,
+    target: T,
+    options: &SyncOptions,
+) -> Result<SyncSummary> {
+    let source = source.as_ref();
+    let target = target.as_ref();
+    let diff = diff(source, target, options)?;
+    let mut summary = SyncSummary {
+        failed: diff.failed.clone(),
+        dry_run: options.dry_run,
+        ..Default::default()
+    };
+
+    if options.dry_run {
+        summary.copied = diff.added.iter().chain(&diff.modified).cloned().collect();
+        summary.copied.sort();
+
+        if options.delete_extraneous {
+            summary.removed = diff.removed.clone();
+        }
+
+        summary.diff = diff;
+        return Ok(summary);
+    }
+
+    fs::create_dir_all(target)?;
+
+    let transfer_options = TransferOptions::new()
+        .with_overwrite(true)
+        .with_preserve(true, true);
+
+    // sorted so parents are always created before their children
+    let mut changes: Vec<&PathBuf> = diff.added.iter().chain(&diff.modified).collect();
+    changes.sort();
+
+    for relative in changes {
+        let from = source.join(relative);
+        let to = target.join(relative);
+        let result = fs::symlink_metadata(&from)
+            .map_err(Into::into)
+            .and_then(|metadata| {
+                let item = TransferItem {
+                    size: 0,
+                    is_dir: metadata.is_dir(),
+                    is_symlink: metadata.file_type().is_symlink(),
+                    from: from.clone(),
+                    to: to.clone(),
+                };
+                clear_sync_target(&item, options.delete_extraneous)?;
+
+                if item.is_symlink {
+                    copy_link(&item)
+                } else if item.is_dir {
+                    fs::create_dir_all(&to).map_err(Into::into)
+                } else {
+                    copy_file(
+                        &item,
+                        &transfer_options,
+                        &NoProgress,
+                        &mut TransferProgress::default(),
+                    )
+                }
+            });
+
+        match result {
+            Ok(_) => summary.copied.push(relative.clone()),
+            Err(e) => summary.failed.push((relative.clone(), e.to_string())),
+        }
+    }
+
+    if options.delete_extraneous {
+        // children first so directories are empty by the time they are removed
+        for relative in diff.removed.iter().rev() {
+            let path = target.join(relative);
+            let result = if path.is_dir() {
+                fs::remove_dir_all(&path)
+            } else if path.exists() || path.is_symlink() {
+                fs::remove_file(&path)
+            } else {
+                Ok(())
+            };
+
+            match result {
+                Ok(_) => summary.removed.push(relative.clone()),
+                Err(e) => summary.failed.push((relative.clone(), e.to_string())),
+            }
+        }
+    }
+
+    summary.diff = diff;
+    Ok(summary)
+}
+
+// makes room for a changed entry without ever writing through a link in the target
+fn clear_sync_target(item: &TransferItem, delete_extraneous: bool) -> Result<()> {
+    let Ok(metadata) = fs::symlink_metadata(&item.to) else {
+        return Ok(());
+    };
+
+    if metadata.file_type().is_symlink() {
+        fs::remove_file(&item.to)?;
+    } else if metadata.is_dir() && !item.is_dir {
+        // the directory is extraneous to the source, so it is only removed when asked to
+        if !delete_extraneous {
+            return Err(InvalidOperationError(format!(
+                "{} is a directory in the target",
+                item.to.display()
+            ))
+            .into());
+        }
+
+        fs::remove_dir_all(&item.to)?;
+    } else if !metadata.is_dir() && item.is_dir {
+        fs::remove_file(&item.to)?;
+    }
+
+    Ok(())
+}
+
+fn collect_sync_entries(
+    path: &Path,
+    options: &SyncOptions,
+    failed: &mut Vec<(PathBuf, String)>,
+) -> Result<BTreeMap<PathBuf, SyncEntry>> {
+    let walk_options = WalkOptions::new().with_exclude(&options.exclude);
+    let mut entries = BTreeMap::new();
+
+    for entry in walk_with(path, &walk_options)? {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                let relative = e
+                    .downcast_ref::<WalkError>()
+                    .and_then(|e| e.0.strip_prefix(path).ok())
+                    .map(Path::to_path_buf)
+                    .unwrap_or_default();
+                failed.push((relative, e.to_string()));
+                continue;
+            }
+        };
+        let relative = entry.path.strip_prefix(path)?.to_path_buf();
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                failed.push((relative, e.to_string()));
+                continue;
+            }
+        };
+        entries.insert(
+            relative,
+            SyncEntry {
+                is_dir: metadata.is_dir(),
+                is_symlink: entry.is_symlink,
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+            },
+        );
+    }
+
+    Ok(entries)
+}
+
+fn is_same_file(
+    source: &Path,
+    source_entry: &SyncEntry,
+    target: &Path,
+    target_entry: &SyncEntry,
+    compare: CompareMethod,
+) -> Result<bool> {
+    // links are compared by where they point, never by what they point to
+    if source_entry.is_symlink || target_entry.is_symlink {
+        if !source_entry.is_symlink || !target_entry.is_symlink {
+            return Ok(false);
+        }
+
+        return Ok(fs::read_link(source)? == fs::read_link(target)?);
+    }
+
+    if source_entry.size != target_entry.size {
+        return Ok(false);
+    }
+
+    match compare {
+        CompareMethod::SizeAndTime => {
+            let (Some(source_time), Some(target_time)) =
+                (source_entry.modified, target_entry.modified)
+            else {
+                return Ok(false);
+            };
+            // some file systems only keep whole seconds
+            let seconds = |time: SystemTime| {
+                time.duration_since(SystemTime::UNIX_EPOCH)
+                    .map_or(0, |e| e.as_secs())
+            };
+            Ok(seconds(source_time) == seconds(target_time))
+        }
+        CompareMethod::Content => {
+            let mut source = BufReader::new(fs::File::open(source)?);
+            let mut target = BufReader::new(fs::File::open(target)?);
+            let mut source_buffer = vec![0u8; TRANSFER_BUFFER_DEF];
+            let mut target_buffer = vec![0u8; TRANSFER_BUFFER_DEF];
+
+            loop {
+                let n = read_full(&mut source, &mut source_buffer)?;
+                let m = read_full(&mut target, &mut target_buffer)?;
+
+                if n != m || source_buffer[..n] != target_buffer[..m] {
+                    return Ok(false);
+                }
+
+                if n == 0 {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+}
+
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize> {
+    let mut read = 0;
+
+    while read < buffer.len() {
+        let n = reader.read(&mut buffer[read..])?;
+
+        if n == 0 {
+            break;
+        }
+
+        read += n;
+    }
+
+    Ok(read)
+}
+
+pub fn ren<F: AsRef<Path>, T: AsRef<str>>(from: F, to: T) -> Result<()> {
+    let to = to.as_ref();
+
+    if to.contains(std::path::is_separator) {
+        let to = Path::new(to);
+        return fs::rename(from, to).map_err(Into::into);
+    }
+
+    let from = from.as_ref();
+    let parent = from.parent().unwrap();
+    let to = parent.join(to);
+    fs::rename(from, to).map_err(Into::into)
+}
+
+pub fn del<T: AsRef<Path>>(path: T) -> Result<()> {
+    let path = path.as_ref();
+
+    if path.is_dir() {
+        fs::remove_dir_all(path).map_err(Into::into)
+    } else {
+        fs::remove_file(path).map_err(Into::into)
+    }
+}
+
+pub fn del_match<T: AsRef<Path>>(path: T, pattern: &str) -> Result<()> {
+    let path = path.as_ref();
+
+    if !path.is_dir() {
+        if path.is_file() {
+            return Err(InvalidDirectoryError(path.to_string_lossy().into_owned()).into());
+        }
+        return Ok(());
+    }
+
+    if pattern.is_empty() {
+        return Err(InvalidOperationError("Empty source path".to_string()).into());
+    }
+
+    let pattern = format!("{}/{}", path.to_string_lossy(), pattern);
+    let paths = glob_with(&pattern, glob_defaults())?;
+
+    for entry in paths.filter_map(|e| e.ok()) {
+        if entry.is_dir() {
+            fs::remove_dir_all(entry)?;
+        } else {
+            fs::remove_file(entry)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn walk<T: AsRef<Path>>(path: T) -> Result<impl Iterator<Item = Result<WalkEntry>>> {
+    walk_with(path, &WalkOptions::default())
+}
+
+// a directory yields the entries under it but not itself, a file yields the file itself
+pub fn walk_with<T: AsRef<Path>>(
+    path: T,
+    options: &WalkOptions,
+) -> Result<impl Iterator<Item = Result<WalkEntry>>> {
+    Walker::new(path.as_ref(), options)
+}
+
+// every directory is listed in its own task on the rayon pool, the entries come in no order
+#[cfg(feature = "threading")]
+pub(crate) fn walk_par<T: AsRef<Path>, F: Fn(Result<WalkEntry>) + Sync>(
+    path: T,
+    options: &WalkOptions,
+    callback: F,
+) -> Result<()> {
+    let walker = Walker::new(path.as_ref(), options)?;
+    rayon::in_place_scope(|scope| walker.drive(scope, &callback));
+    Ok(())
+}
+
+// include and exclude patterns follow the .gitignore rules: a pattern without a slash
+// matches the name at any depth, one with a slash is matched from the root
+#[derive(Clone)]
+struct WalkPattern {
+    pattern: glob::Pattern,
+    anchored: bool,
+    dir_only: bool,
+}
+
+impl WalkPattern {
+    fn parse_all(patterns: &[String]) -> Result<Vec<Self>> {
+        patterns.iter().map(|e| Self::parse(e)).collect()
+    }
+
+    fn parse(pattern: &str) -> Result<Self> {
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        let anchored = pattern.contains('/');
+        Ok(Self {
+            pattern: glob::Pattern::new(pattern.trim_start_matches('/'))?,
+            anchored,
+            dir_only,
+        })
+    }
+
+    fn matches(&self, relative: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..glob_defaults()
+        };
+
+        if self.anchored {
+            return self.pattern.matches_path_with(relative, options);
+        }
+
+        relative
+            .file_name()
+            .is_some_and(|e| self.pattern.matches_with(&e.to_string_lossy(), options))
+    }
+}
+
+struct WalkLevel {
+    entries: std::vec::IntoIter<PathBuf>,
+    depth: usize,
+    // kept when links are followed to detect a link that points back to one of its parents
+    real_path: Option<PathBuf>,
+    #[cfg(feature = "gitignore")]
+    ignore: Option<Arc<Gitignore>>,
+}
+
+impl WalkLevel {
+    // the parent levels are only needed for the ignore files and the loop detection
+    fn context(&self) -> Self {
+        WalkLevel {
+            entries: Vec::new().into_iter(),
+            depth: self.depth,
+            real_path: self.real_path.clone(),
+            #[cfg(feature = "gitignore")]
+            ignore: self.ignore.clone(),
+        }
+    }
+}
+
+struct Walker {
+    root: PathBuf,
+    options: WalkOptions,
+    include: Vec<WalkPattern>,
+    exclude: Vec<WalkPattern>,
+    levels: Vec<WalkLevel>,
+    // entries and errors that are due before the next directory entry
+    pending: VecDeque<Result<WalkEntry>>,
+    #[cfg(feature = "gitignore")]
+    real_root: PathBuf,
+    #[cfg(feature = "gitignore")]
+    parents: Vec<Gitignore>,
+}
+
+impl Walker {
+    fn new(path: &Path, options: &WalkOptions) -> Result<Self> {
+        if !path.exists() {
+            return Err(NotFoundError(path.to_string_lossy().into_owned()).into());
+        }
+
+        let mut walker = Walker {
+            root: path.to_path_buf(),
+            options: options.clone(),
+            include: WalkPattern::parse_all(&options.include)?,
+            exclude: WalkPattern::parse_all(&options.exclude)?,
+            levels: Vec::new(),
+            pending: VecDeque::new(),
+            #[cfg(feature = "gitignore")]
+            real_root: PathBuf::new(),
+            #[cfg(feature = "gitignore")]
+            parents: Vec::new(),
+        };
+
+        if path.is_dir() {
+            #[cfg(feature = "gitignore")]
+            walker.load_parents();
+            walker.push_dir(path, 1);
+        } else if let Some(entry) = walker.visit(path.to_path_buf(), 0) {
+            walker.pending.push_back(entry);
+        }
+
+        Ok(walker)
+    }
+
+    fn push_dir(&mut self, dir: &Path, depth: usize) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.pending
+                    .push_back(Err(WalkError(dir.to_path_buf(), e).into()));
+                return;
+            }
+        };
+        let mut paths = Vec::new();
+
+        for entry in entries {
+            match entry {
+                Ok(entry) => paths.push(entry.path()),
+                Err(e) => self
+                    .pending
+                    .push_back(Err(WalkError(dir.to_path_buf(), e).into())),
+            }
+        }
+
+        if self.options.sorted {
+            paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+        }
+
+        let real_path = match self.options.symlinks {
+            SymlinkPolicy::Follow => fs::canonicalize(dir).ok(),
+            _ => None,
+        };
+        self.levels.push(WalkLevel {
+            entries: paths.into_iter(),
+            depth,
+            real_path,
+            #[cfg(feature = "gitignore")]
+            ignore: ignore_matcher(dir, &self.options).map(Arc::new),
+        });
+    }
+
+    fn visit(&mut self, path: PathBuf, depth: usize) -> Option<Result<WalkEntry>> {
+        if depth > 0 && !self.options.include_hidden && is_hidden(&path) {
+            return None;
+        }
+
+        let metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) => return Some(Err(WalkError(path, e).into())),
+        };
+        let is_symlink = metadata.file_type().is_symlink();
+        let file_type = match self.options.symlinks {
+            SymlinkPolicy::Skip if is_symlink => return None,
+            SymlinkPolicy::Follow if is_symlink => match fs::metadata(&path) {
+                Ok(metadata) => metadata.file_type(),
+                Err(e) => return Some(Err(WalkError(path, e).into())),
+            },
+            _ => metadata.file_type(),
+        };
+        let entry = WalkEntry {
+            path,
+            depth,
+            file_type: Some(file_type),
+            is_symlink,
+        };
+        let is_dir = entry.is_dir();
+        let relative = match entry.path.strip_prefix(&self.root) {
+            Ok(relative) if !relative.as_os_str().is_empty() => relative,
+            _ => Path::new(entry.path.file_name().unwrap_or_default()),
+        };
+
+        if self.exclude.iter().any(|e| e.matches(relative, is_dir)) {
+            return None;
+        }
+
+        #[cfg(feature = "gitignore")]
+        if self.is_ignored(&entry.path, relative, is_dir) {
+            return None;
+        }
+
+        // directories are still descended into but only listed if they match the include patterns
+        let included =
+            self.include.is_empty() || self.include.iter().any(|e| e.matches(relative, is_dir));
+
+        if is_dir && self.options.max_depth.is_none_or(|e| depth < e) {
+            if is_symlink && self.is_loop(&entry.path) {
+                let message = format!("Symlink loop: {}", entry.path.display());
+                self.pending
+                    .push_back(Err(InvalidOperationError(message).into()));
+            } else {
+                self.push_dir(&entry.path, depth + 1);
+            }
+        }
+
+        if !included {
+            return None;
+        }
+
+        match self.options.kind {
+            EntryKind::Files if is_dir => return None,
+            EntryKind::Directories if !is_dir => return None,
+            _ => {}
+        }
+
+        if is_dir || !self.options.has_metadata_filters() {
+            return Some(Ok(entry));
+        }
+
+        match entry.metadata() {
+            Ok(metadata) if self.options.matches_metadata(&metadata) => Some(Ok(entry)),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    #[cfg(feature = "threading")]
+    fn drive<'s, F: Fn(Result<WalkEntry>) + Sync>(
+        mut self,
+        scope: &rayon::Scope<'s>,
+        callback: &'s F,
+    ) {
+        // the top level belongs to this walker, the ones below it are only context
+        let base = self.levels.len();
+
+        loop {
+            while let Some(item) = self.pending.pop_front() {
+                callback(item);
+            }
+
+            let Some(level) = self.levels.last_mut() else {
+                return;
+            };
+            let depth = level.depth;
+            let Some(path) = level.entries.next() else {
+                return;
+            };
+
+            if let Some(item) = self.visit(path, depth) {
+                callback(item);
+            }
+
+            if self.levels.len() > base {
+                let level = self.levels.pop().unwrap();
+                let fork = self.fork(level);
+                scope.spawn(move |scope| fork().drive(scope, callback));
+            }
+        }
+    }
+
+    // the walker is not Send because of the pending errors, so only its parts are moved
+    #[cfg(feature = "threading")]
+    fn fork(&self, level: WalkLevel) -> impl FnOnce() -> Walker + Send {
+        let mut levels: Vec<WalkLevel> = self.levels.iter().map(WalkLevel::context).collect();
+        levels.push(level);
+        let root = self.root.clone();
+        let options = self.options.clone();
+        let include = self.include.clone();
+        let exclude = self.exclude.clone();
+        #[cfg(feature = "gitignore")]
+        let real_root = self.real_root.clone();
+        #[cfg(feature = "gitignore")]
+        let parents = self.parents.clone();
+
+        move || Walker {
+            root,
+            options,
+            include,
+            exclude,
+            levels,
+            pending: VecDeque::new(),
+            #[cfg(feature = "gitignore")]
+            real_root,
+            #[cfg(feature = "gitignore")]
+            parents,
+        }
+    }
+
+    fn is_loop(&self, path: &Path) -> bool {
+        let Ok(real_path) = fs::canonicalize(path) else {
+            return false;
+        };
+        self.levels
+            .iter()
+            .any(|e| e.real_path.as_ref() == Some(&real_path))
+    }
+}
+
+#[cfg(feature = "gitignore")]
+impl Walker {
+    // ignore files in the directories above the root apply as well, the nearest first
+    fn load_parents(&mut self) {
+        if !self.options.git_ignore && self.options.ignore_files.is_empty() {
+            return;
+        }
+
+        let Ok(real_root) = fs::canonicalize(&self.root) else {
+            return;
+        };
+        self.parents = real_root
+            .ancestors()
+            .skip(1)
+            .filter_map(|e| ignore_matcher(e, &self.options))
+            .collect();
+        self.real_root = real_root;
+    }
+
+    fn is_ignored(&self, path: &Path, relative: &Path, is_dir: bool) -> bool {
+        let levels = self.levels.iter().rev().filter_map(|e| e.ignore.as_deref());
+        let real_path = self.real_root.join(relative);
+        let parents = self.parents.iter();
+
+        for (ignore, path) in levels
+            .map(|e| (e, path))
+            .chain(parents.map(|e| (e, real_path.as_path())))
+        {
+            match ignore.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+
+        false
+    }
+}
+
+impl Iterator for Walker {
+    type Item = Result<WalkEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                return Some(item);
+            }
+
+            let level = self.levels.last_mut()?;
+            let depth = level.depth;
+            let Some(path) = level.entries.next() else {
+                self.levels.pop();
+                continue;
+            };
+
+            if let Some(item) = self.visit(path, depth) {
+                // anything queued while visiting belongs after this entry
+                self.pending.push_front(item);
+            }
+        }
+    }
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|e| e.to_string_lossy().starts_with('.'))
+}
+
+#[cfg(feature = "gitignore")]
+fn ignore_matcher(dir: &Path, options: &WalkOptions) -> Option<Gitignore> {
+    let mut files = Vec::new();
+
+    if options.git_ignore {
+        // applied even outside of a git repository
+        files.push(dir.join(".git").join("info").join("exclude"));
+        files.push(dir.join(".gitignore"));
+    }
+
+    files.extend(options.ignore_files.iter().map(|e| dir.join(e)));
+
+    let mut builder = GitignoreBuilder::new(dir);
+    let mut found = false;
+
+    // later files take precedence, a bad line only drops that line
+    for file in files.iter().filter(|e| e.is_file()) {
+        builder.add(file);
+        found = true;
+    }
+
+    if !found {
+        return None;
+    }
+
+    builder.build().ok()
 }
@@ -0,0 +1,403 @@
+use futures::Stream;
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use crate::Result;
+
+const INTERVAL_DEF: Duration = Duration::from_millis(250);
+// more generations than any of the log module rollers keep
+const GENERATIONS_MAX: usize = 16;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RotationNaming {
+    #[default]
+    Auto,
+    // log4rs FixedWindowRoller: app.log -> app.0.old.log
+    FixedWindow,
+    // file-rotate AppendCount: app.log -> app.log.1
+    AppendCount,
+    None,
+}
+
+impl RotationNaming {
+    pub fn rotated_paths<T: AsRef<Path>>(&self, path: T) -> Vec<PathBuf> {
+        let path = path.as_ref();
+        let folder = path.parent().unwrap_or(Path::new(""));
+        let Some(name) = path.file_name().map(|e| e.to_string_lossy().into_owned()) else {
+            return Vec::new();
+        };
+        let fixed_window = || {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let extension = path.extension().unwrap_or_default().to_string_lossy();
+            (0..GENERATIONS_MAX)
+                .map(|i| folder.join(format!("{}.{}.old.{}", stem, i, extension)))
+                .collect::<Vec<_>>()
+        };
+        let append_count = || {
+            (1..=GENERATIONS_MAX)
+                .map(|i| folder.join(format!("{}.{}", name, i)))
+                .collect::<Vec<_>>()
+        };
+
+        match self {
+            RotationNaming::FixedWindow => fixed_window(),
+            RotationNaming::AppendCount => append_count(),
+            RotationNaming::Auto => {
+                let fixed_window = fixed_window();
+
+                if fixed_window.iter().any(|e| e.exists()) {
+                    fixed_window
+                } else {
+                    append_count()
+                }
+            }
+            RotationNaming::None => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for RotationNaming {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RotationNaming::Auto => write!(f, "Auto"),
+            RotationNaming::FixedWindow => write!(f, "FixedWindow"),
+            RotationNaming::AppendCount => write!(f, "AppendCount"),
+            RotationNaming::None => write!(f, "None"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FollowOptions {
+    pub from_start: bool,
+    pub interval: Duration,
+    pub naming: RotationNaming,
+}
+
+impl Default for FollowOptions {
+    fn default() -> Self {
+        FollowOptions {
+            from_start: false,
+            interval: INTERVAL_DEF,
+            naming: RotationNaming::Auto,
+        }
+    }
+}
+
+impl FollowOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_from_start(&self, from_start: bool) -> Self {
+        FollowOptions {
+            from_start,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_interval(&self, interval: Duration) -> Self {
+        FollowOptions {
+            interval,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_naming(&self, naming: RotationNaming) -> Self {
+        FollowOptions {
+            naming,
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FileId {
+    #[cfg(unix)]
+    dev: u64,
+    #[cfg(unix)]
+    ino: u64,
+    // the creation time is not enough, windows tunneling gives a file recreated right after
+    // a rename the creation time of the old one
+    #[cfg(windows)]
+    volume: u64,
+    #[cfg(windows)]
+    index: u64,
+    #[cfg(not(any(unix, windows)))]
+    created: Option<std::time::SystemTime>,
+}
+
+impl FileId {
+    #[cfg(unix)]
+    fn of(file: &File) -> io::Result<Self> {
+        Ok(Self::from_metadata(&file.metadata()?))
+    }
+
+    #[cfg(windows)]
+    fn of(file: &File) -> io::Result<Self> {
+        let info = winapi_util::file::information(file)?;
+        Ok(FileId {
+            volume: info.volume_serial_number(),
+            index: info.file_index(),
+        })
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn of(file: &File) -> io::Result<Self> {
+        Ok(Self::from_metadata(&file.metadata()?))
+    }
+
+    #[cfg(unix)]
+    fn from_metadata(metadata: &std::fs::Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+        FileId {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+        }
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn from_metadata(metadata: &std::fs::Metadata) -> Self {
+        FileId {
+            created: metadata.created().ok(),
+        }
+    }
+}
+
+// the identity and the length of the file at a path
+fn identify(path: &Path) -> io::Result<(FileId, u64)> {
+    #[cfg(windows)]
+    {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        Ok((FileId::of(&file)?, len))
+    }
+
+    #[cfg(not(windows))]
+    {
+        let metadata = std::fs::metadata(path)?;
+        Ok((FileId::from_metadata(&metadata), metadata.len()))
+    }
+}
+
+#[derive(Debug)]
+struct Source {
+    reader: BufReader<File>,
+    id: FileId,
+    position: u64,
+}
+
+impl Source {
+    fn open(path: &Path, from_start: bool) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let id = FileId::of(&file)?;
+        let position = match from_start {
+            true => 0,
+            false => file.seek(SeekFrom::End(0))?,
+        };
+        Ok(Self {
+            reader: BufReader::new(file),
+            id,
+            position,
+        })
+    }
+
+    fn from_file(file: File) -> io::Result<Self> {
+        let mut file = file;
+        let id = FileId::of(&file)?;
+        let position = file.stream_position()?;
+        Ok(Self {
+            reader: BufReader::new(file),
+            id,
+            position,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Follower {
+    path: PathBuf,
+    options: FollowOptions,
+    source: Option<Source>,
+    lines: VecDeque<String>,
+    partial: Vec<u8>,
+}
+
+impl Follower {
+    pub fn new<T: AsRef<Path>>(path: T) -> Result<Self> {
+        Self::with_options(path, &FollowOptions::new())
+    }
+
+    pub fn with_options<T: AsRef<Path>>(path: T, options: &FollowOptions) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        // a file that does not exist yet is picked up once it's created
+        let source = match Source::open(&path, options.from_start) {
+            Ok(source) => Some(source),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path,
+            options: options.clone(),
+            source,
+            lines: VecDeque::new(),
+            partial: Vec::new(),
+        })
+    }
+
+    pub fn from_file<T: AsRef<Path>>(file: File, path: T, options: &FollowOptions) -> Result<Self> {
+        let source = Source::from_file(file)?;
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            options: options.clone(),
+            source: Some(source),
+            lines: VecDeque::new(),
+            partial: Vec::new(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn try_next(&mut self) -> Result<Option<String>> {
+        if self.lines.is_empty() {
+            self.poll()?;
+        }
+
+        Ok(self.lines.pop_front())
+    }
+
+    pub fn iter(&mut self) -> impl Iterator<Item = Result<String>> + '_ {
+        std::iter::from_fn(move || loop {
+            match self.try_next() {
+                Ok(Some(line)) => return Some(Ok(line)),
+                Ok(None) => thread::sleep(self.options.interval),
+                Err(e) => return Some(Err(e)),
+            }
+        })
+    }
+
+    pub fn into_stream(self) -> impl Stream<Item = Result<String>> {
+        futures::stream::unfold(self, |mut follower| async move {
+            loop {
+                match follower.try_next() {
+                    Ok(Some(line)) => return Some((Ok(line), follower)),
+                    Ok(None) => tokio::time::sleep(follower.options.interval).await,
+                    Err(e) => return Some((Err(e), follower)),
+                }
+            }
+        })
+    }
+
+    fn poll(&mut self) -> io::Result<()> {
+        let Some(source) = self.source.as_mut() else {
+            if self.path.exists() {
+                self.source = Some(Source::open(&self.path, true)?);
+                return self.poll();
+            }
+
+            return Ok(());
+        };
+        read_lines(source, &mut self.partial, &mut self.lines)?;
+
+        let (id, len) = match identify(&self.path) {
+            Ok(identity) => identity,
+            // rotated away and the new file is not created yet
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        if id == source.id {
+            if len < source.position {
+                // truncated in place, start over
+                source.reader.seek(SeekFrom::Start(0))?;
+                source.position = 0;
+                self.partial.clear();
+                return read_lines(source, &mut self.partial, &mut self.lines);
+            }
+
+            return Ok(());
+        }
+
+        // rotated, the old file is completely read by now. Any generations rotated
+        // since the last poll sit between the old file and the new one.
+        let old_id = source.id.clone();
+        let rotated = self.options.naming.rotated_paths(&self.path);
+        let skipped = rotated
+            .iter()
+            .position(|e| identify(e).is_ok_and(|(id, _)| id == old_id))
+            .unwrap_or(0);
+
+        for path in rotated[..skipped].iter().rev() {
+            if let Ok(file) = File::open(path) {
+                let mut source = Source::from_file(file)?;
+                read_lines(&mut source, &mut self.partial, &mut self.lines)?;
+            }
+        }
+
+        if !self.partial.is_empty() {
+            let line = String::from_utf8_lossy(&self.partial).into_owned();
+            self.partial.clear();
+            self.lines.push_back(line);
+        }
+
+        let mut source = Source::open(&self.path, true)?;
+        read_lines(&mut source, &mut self.partial, &mut self.lines)?;
+        self.source = Some(source);
+        Ok(())
+    }
+}
+
+impl Iterator for Follower {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter().next()
+    }
+}
+
+pub fn follow<T: AsRef<Path>>(path: T) -> Result<Follower> {
+    Follower::new(path)
+}
+
+pub fn follow_with<T: AsRef<Path>>(path: T, options: &FollowOptions) -> Result<Follower> {
+    Follower::with_options(path, options)
+}
+
+fn read_lines(
+    source: &mut Source,
+    partial: &mut Vec<u8>,
+    lines: &mut VecDeque<String>,
+) -> io::Result<()> {
+    loop {
+        let n = source.reader.read_until(b'\n', partial)?;
+
+        if n == 0 {
+            return Ok(());
+        }
+
+        source.position += n as u64;
+
+        // keep incomplete lines until the writer finishes them
+        if partial.last() != Some(&b'\n') {
+            continue;
+        }
+
+        partial.pop();
+
+        if partial.last() == Some(&b'\r') {
+            partial.pop();
+        }
+
+        lines.push_back(String::from_utf8_lossy(partial).into_owned());
+        partial.clear();
+    }
+}
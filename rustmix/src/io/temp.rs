@@ -0,0 +1,337 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+};
+
+use super::{
+    directory,
+    file::{self, FileOpenOptions},
+};
+use crate::{error::InvalidOperationError, random, Result};
+
+const NAME_LEN: usize = 8;
+const PREFIX_DEF: &str = ".tmp";
+const MAX_TRIES: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TempOptions {
+    pub prefix: String,
+    pub suffix: String,
+    pub parent: Option<PathBuf>,
+}
+
+impl Default for TempOptions {
+    fn default() -> Self {
+        TempOptions {
+            prefix: PREFIX_DEF.to_string(),
+            suffix: String::new(),
+            parent: None,
+        }
+    }
+}
+
+impl TempOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_prefix<T: AsRef<str>>(&self, prefix: T) -> Self {
+        TempOptions {
+            prefix: prefix.as_ref().to_string(),
+            ..self.clone()
+        }
+    }
+
+    pub fn with_suffix<T: AsRef<str>>(&self, suffix: T) -> Self {
+        TempOptions {
+            suffix: suffix.as_ref().to_string(),
+            ..self.clone()
+        }
+    }
+
+    pub fn with_parent<T: AsRef<Path>>(&self, parent: T) -> Self {
+        TempOptions {
+            parent: Some(parent.as_ref().to_path_buf()),
+            ..self.clone()
+        }
+    }
+
+    fn parent(&self) -> PathBuf {
+        match &self.parent {
+            Some(parent) => parent.clone(),
+            None => std::env::temp_dir(),
+        }
+    }
+
+    fn candidate(&self, parent: &Path) -> PathBuf {
+        parent.join(format!(
+            "{}{}{}",
+            self.prefix,
+            random::alphanum_str(NAME_LEN),
+            self.suffix
+        ))
+    }
+}
+
+#[derive(Debug)]
+pub struct TempFile {
+    file: Option<File>,
+    path: PathBuf,
+}
+
+impl TempFile {
+    pub fn new() -> Result<Self> {
+        Self::with_options(&TempOptions::new())
+    }
+
+    pub fn with_options(options: &TempOptions) -> Result<Self> {
+        let parent = options.parent();
+
+        for _ in 0..MAX_TRIES {
+            let path = options.candidate(&parent);
+
+            match file::create_with(&path, FileOpenOptions::New) {
+                Ok(file) => {
+                    return Ok(Self {
+                        file: Some(file),
+                        path,
+                    })
+                }
+                Err(e) if is_already_exists(e.as_ref()) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(InvalidOperationError("Could not create a unique temp file".to_string()).into())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn file(&self) -> &File {
+        self.file.as_ref().unwrap()
+    }
+
+    pub fn file_mut(&mut self) -> &mut File {
+        self.file.as_mut().unwrap()
+    }
+
+    pub fn reopen(&self) -> Result<File> {
+        let mut options = OpenOptions::new();
+        options.read(true).write(true);
+        file::from_options(&self.path, &options)
+    }
+
+    pub fn persist(mut self) -> PathBuf {
+        self.file.take();
+        std::mem::take(&mut self.path)
+    }
+
+    pub fn persist_to<T: AsRef<Path>>(mut self, path: T) -> Result<PathBuf> {
+        let target = path.as_ref().to_path_buf();
+
+        if let Some(dir) = target.parent() {
+            if !dir.as_os_str().is_empty() {
+                directory::ensure(dir)?;
+            }
+        }
+
+        let file = self.file.take().unwrap();
+        drop(file);
+        move_path(&self.path, &target)?;
+        self.path = PathBuf::new();
+        Ok(target)
+    }
+}
+
+impl Deref for TempFile {
+    type Target = File;
+
+    fn deref(&self) -> &Self::Target {
+        self.file()
+    }
+}
+
+impl DerefMut for TempFile {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.file_mut()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        // an empty path means the file was persisted
+        if self.path.as_os_str().is_empty() {
+            return;
+        }
+
+        drop(self.file.take());
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[derive(Debug)]
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new() -> Result<Self> {
+        Self::with_options(&TempOptions::new())
+    }
+
+    pub fn with_options(options: &TempOptions) -> Result<Self> {
+        let parent = options.parent();
+        directory::ensure(&parent)?;
+
+        for _ in 0..MAX_TRIES {
+            let path = options.candidate(&parent);
+
+            match fs::create_dir(&path) {
+                Ok(_) => return Ok(Self { path }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Err(InvalidOperationError("Could not create a unique temp directory".to_string()).into())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join<T: AsRef<Path>>(&self, path: T) -> PathBuf {
+        self.path.join(path)
+    }
+
+    pub fn create_file<T: AsRef<Path>>(&self, path: T) -> Result<File> {
+        self.create_file_with(path, FileOpenOptions::Default)
+    }
+
+    pub fn create_file_with<T: AsRef<Path>>(
+        &self,
+        path: T,
+        options: FileOpenOptions,
+    ) -> Result<File> {
+        file::create_with(self.join(path), options)
+    }
+
+    pub fn temp_file(&self) -> Result<TempFile> {
+        TempFile::with_options(&TempOptions::new().with_parent(&self.path))
+    }
+
+    pub fn persist(mut self) -> PathBuf {
+        std::mem::take(&mut self.path)
+    }
+
+    pub fn persist_to<T: AsRef<Path>>(mut self, path: T) -> Result<PathBuf> {
+        let target = path.as_ref().to_path_buf();
+
+        if let Some(dir) = target.parent() {
+            if !dir.as_os_str().is_empty() {
+                directory::ensure(dir)?;
+            }
+        }
+
+        move_path(&self.path, &target)?;
+        self.path = PathBuf::new();
+        Ok(target)
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempFile {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if self.path.as_os_str().is_empty() {
+            return;
+        }
+
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+pub fn file() -> Result<TempFile> {
+    TempFile::new()
+}
+
+pub fn dir() -> Result<TempDir> {
+    TempDir::new()
+}
+
+fn is_already_exists(e: &(dyn std::error::Error + 'static)) -> bool {
+    e.downcast_ref::<io::Error>()
+        .is_some_and(|e| e.kind() == io::ErrorKind::AlreadyExists)
+}
+
+fn move_path(from: &Path, to: &Path) -> Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    // rename does not work across file systems
+    if from.is_dir() {
+        fs::create_dir(to)?;
+
+        // a partial copy is not left behind
+        if let Err(e) = copy_tree(from, to) {
+            let _ = fs::remove_dir_all(to);
+            return Err(e);
+        }
+
+        fs::remove_dir_all(from)?;
+    } else {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+
+    Ok(())
+}
+
+fn copy_tree(from: &Path, to: &Path) -> Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        let kind = entry.file_type()?;
+
+        if kind.is_dir() {
+            fs::create_dir(&target)?;
+            copy_tree(&entry.path(), &target)?;
+        } else if kind.is_symlink() {
+            copy_link(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn copy_link(from: &Path, to: &Path) -> Result<()> {
+    let link = fs::read_link(from)?;
+
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(&link, to)?;
+
+    #[cfg(windows)]
+    match from.is_dir() {
+        true => std::os::windows::fs::symlink_dir(&link, to)?,
+        false => std::os::windows::fs::symlink_file(&link, to)?,
+    }
+
+    Ok(())
+}
@@ -0,0 +1,285 @@
+use futures::Stream;
+use glob::Pattern;
+use notify_debouncer_full::{
+    new_debouncer,
+    notify::{
+        event::{ModifyKind, RenameMode},
+        Error as NotifyError, EventKind, RecommendedWatcher, RecursiveMode,
+    },
+    DebounceEventResult, Debouncer, RecommendedCache,
+};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    result::Result as StdResult,
+    thread,
+    time::Duration,
+};
+use tokio::{
+    runtime,
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    time,
+};
+
+use super::path::glob_defaults;
+use crate::{
+    threading::{Consumer, StaticTaskItem, INTERVAL},
+    Result,
+};
+
+const DEBOUNCE_DEF: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WatchEventKind {
+    Create,
+    Modify,
+    Remove,
+    Rename,
+}
+
+impl fmt::Display for WatchEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WatchEventKind::Create => write!(f, "Create"),
+            WatchEventKind::Modify => write!(f, "Modify"),
+            WatchEventKind::Remove => write!(f, "Remove"),
+            WatchEventKind::Rename => write!(f, "Rename"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WatchEvent {
+    pub kind: WatchEventKind,
+    pub paths: Vec<PathBuf>,
+}
+
+impl WatchEvent {
+    pub fn path(&self) -> Option<&PathBuf> {
+        self.paths.last()
+    }
+}
+
+impl fmt::Display for WatchEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.kind, self.paths.as_slice()) {
+            (WatchEventKind::Rename, [from, to]) => {
+                write!(f, "{}: {} -> {}", self.kind, from.display(), to.display())
+            }
+            (_, [path, ..]) => write!(f, "{}: {}", self.kind, path.display()),
+            _ => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchOptions {
+    pub recursive: bool,
+    pub debounce: Duration,
+    pub patterns: Vec<String>,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            recursive: true,
+            debounce: DEBOUNCE_DEF,
+            patterns: Vec::new(),
+        }
+    }
+}
+
+impl WatchOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_recursive(&self, recursive: bool) -> Self {
+        WatchOptions {
+            recursive,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_debounce(&self, debounce: Duration) -> Self {
+        WatchOptions {
+            debounce,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_patterns<T: AsRef<str>>(&self, patterns: &[T]) -> Self {
+        WatchOptions {
+            patterns: patterns.iter().map(|e| e.as_ref().to_string()).collect(),
+            ..self.clone()
+        }
+    }
+}
+
+pub struct Watcher {
+    debouncer: Debouncer<RecommendedWatcher, RecommendedCache>,
+    receiver: UnboundedReceiver<StdResult<WatchEvent, NotifyError>>,
+    recursive: bool,
+}
+
+impl Watcher {
+    pub fn new() -> Result<Self> {
+        Self::with_options(&WatchOptions::default())
+    }
+
+    pub fn with_options(options: &WatchOptions) -> Result<Self> {
+        let patterns = options
+            .patterns
+            .iter()
+            .map(|e| Pattern::new(e))
+            .collect::<StdResult<Vec<_>, _>>()?;
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let debouncer = new_debouncer(options.debounce, None, move |result| {
+            dispatch(result, &patterns, &sender)
+        })?;
+        Ok(Self {
+            debouncer,
+            receiver,
+            recursive: options.recursive,
+        })
+    }
+
+    pub fn watch<T: AsRef<Path>>(&mut self, path: T) -> Result<()> {
+        let mode = if self.recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        self.debouncer.watch(path, mode).map_err(Into::into)
+    }
+
+    pub fn unwatch<T: AsRef<Path>>(&mut self, path: T) -> Result<()> {
+        self.debouncer.unwatch(path).map_err(Into::into)
+    }
+
+    pub fn try_recv(&mut self) -> Option<Result<WatchEvent>> {
+        self.receiver.try_recv().ok().map(|e| e.map_err(Into::into))
+    }
+
+    pub async fn recv(&mut self) -> Option<Result<WatchEvent>> {
+        self.receiver.recv().await.map(|e| e.map_err(Into::into))
+    }
+
+    // blocks the calling thread, so it must not be called from async code where blocking_recv
+    // panics. use recv or into_stream there instead
+    pub fn iter(&mut self) -> impl Iterator<Item = Result<WatchEvent>> + '_ {
+        std::iter::from_fn(move || self.receiver.blocking_recv().map(|e| e.map_err(Into::into)))
+    }
+
+    pub fn into_stream(self) -> impl Stream<Item = Result<WatchEvent>> {
+        let Watcher {
+            debouncer,
+            mut receiver,
+            ..
+        } = self;
+        futures::stream::poll_fn(move |cx| {
+            // keep the debouncer alive as long as the stream is
+            let _ = &debouncer;
+            receiver
+                .poll_recv(cx)
+                .map(|e| e.map(|e| e.map_err(Into::into)))
+        })
+    }
+
+    // the events are received on a thread of its own, errors are logged and the feed goes on
+    pub fn feed<T: StaticTaskItem + From<WatchEvent>>(
+        self,
+        consumer: &Consumer<T>,
+    ) -> thread::JoinHandle<()> {
+        let consumer = consumer.clone();
+        let Watcher {
+            debouncer,
+            mut receiver,
+            ..
+        } = self;
+        thread::spawn(move || {
+            let _debouncer = debouncer;
+            let runtime = match runtime::Builder::new_current_thread().enable_time().build() {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    log::error!("Cannot start the watcher feed. {}", e);
+                    return;
+                }
+            };
+            // the timeout only lets the thread notice a consumer that was stopped meanwhile
+            let interval = Duration::from_millis(INTERVAL);
+
+            while !consumer.is_cancelled() && !consumer.is_completed() {
+                let received =
+                    runtime.block_on(async { time::timeout(interval, receiver.recv()).await });
+
+                match received {
+                    Ok(Some(Ok(event))) => {
+                        if consumer.enqueue(event.into()).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(Some(Err(e))) => log::warn!("Watcher error. {}", e),
+                    Ok(None) => break,
+                    Err(_) => {}
+                }
+            }
+        })
+    }
+}
+
+fn dispatch(
+    result: DebounceEventResult,
+    patterns: &[Pattern],
+    sender: &UnboundedSender<StdResult<WatchEvent, NotifyError>>,
+) {
+    let events = match result {
+        Ok(events) => events,
+        Err(errors) => {
+            for e in errors {
+                let _ = sender.send(Err(e));
+            }
+
+            return;
+        }
+    };
+
+    for event in events {
+        let kind = match event.kind {
+            EventKind::Create(_) => WatchEventKind::Create,
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => WatchEventKind::Rename,
+            // moved in or out of the watched tree
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => WatchEventKind::Create,
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => WatchEventKind::Remove,
+            EventKind::Modify(_) => WatchEventKind::Modify,
+            EventKind::Remove(_) => WatchEventKind::Remove,
+            _ => continue,
+        };
+
+        if !patterns.is_empty() && !event.paths.iter().any(|e| is_match(e, patterns)) {
+            continue;
+        }
+
+        let event = WatchEvent {
+            kind,
+            paths: event.event.paths,
+        };
+
+        if sender.send(Ok(event)).is_err() {
+            return;
+        }
+    }
+}
+
+fn is_match(path: &Path, patterns: &[Pattern]) -> bool {
+    let options = glob_defaults();
+    let name = path.file_name().map(Path::new);
+    patterns.iter().any(|pattern| {
+        // patterns without a separator match the file name, same as a glob inside a folder
+        if pattern.as_str().contains(std::path::is_separator) {
+            pattern.matches_path_with(path, options)
+        } else {
+            name.is_some_and(|e| pattern.matches_path_with(e, options))
+        }
+    })
+}
@@ -1,6 +1,8 @@
+use futures::Future;
 pub use indicatif::*;
 use std::{
     borrow::Cow,
+    fmt,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
@@ -8,9 +10,13 @@ use std::{
     thread,
     time::Duration,
 };
+use tokio::time;
 
 use super::INTERVAL;
-use crate::{error::InvalidOperationError, Result};
+use crate::{
+    error::{InvalidOperationError, TimedoutError},
+    Result,
+};
 
 const ERR_FINISHED: &str = "Spinner is already finished. Try resseting it.";
 
@@ -22,6 +28,8 @@ pub struct SpinnerOptions {
     pub position: Option<u64>,
     pub style: Option<ProgressStyle>,
     pub steady_ticks: Option<u64>,
+    pub success_style: Option<ProgressStyle>,
+    pub failure_style: Option<ProgressStyle>,
 }
 
 impl Default for SpinnerOptions {
@@ -37,67 +45,100 @@ impl Default for SpinnerOptions {
                     .tick_chars("⣾⣽⣻⢿⡿⣟⣯⣷"),
             ),
             steady_ticks: Some(INTERVAL),
+            success_style: Some(ProgressStyle::with_template("{prefix}{msg:.green}").unwrap()),
+            failure_style: Some(ProgressStyle::with_template("{prefix}{msg:.red}").unwrap()),
         }
     }
 }
 
+#[derive(Clone)]
+struct SpinnerStyles {
+    running: Option<ProgressStyle>,
+    success: Option<ProgressStyle>,
+    failure: Option<ProgressStyle>,
+}
+
+impl fmt::Debug for SpinnerStyles {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SpinnerStyles")
+            .field("running", &self.running.is_some())
+            .field("success", &self.success.is_some())
+            .field("failure", &self.failure.is_some())
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SpinnerHandle {
+    pb: ProgressBar,
+}
+
+impl SpinnerHandle {
+    pub fn message(&self) -> String {
+        self.pb.message().to_string()
+    }
+
+    pub fn set_message(&self, message: impl Into<Cow<'static, str>>) {
+        self.pb.set_message(message);
+    }
+
+    pub fn prefix(&self) -> String {
+        self.pb.prefix().to_string()
+    }
+
+    pub fn set_prefix(&self, prefix: impl Into<Cow<'static, str>>) {
+        self.pb.set_prefix(prefix);
+    }
+
+    pub fn println<I: AsRef<str>>(&self, message: I) {
+        self.pb.println(message);
+    }
+
+    pub fn tick(&self) {
+        self.pb.tick();
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Spinner {
     pb: ProgressBar,
+    styles: Arc<SpinnerStyles>,
     is_finished: Arc<AtomicBool>,
 }
 
 impl Spinner {
     pub fn new() -> Self {
         let pb = ProgressBar::new_spinner();
-        Self {
-            pb: Self::setup(pb, SpinnerOptions::default()),
-            is_finished: Arc::new(AtomicBool::new(false)),
-        }
+        Self::build(pb, SpinnerOptions::default())
     }
 
     pub fn with_prefix(prefix: String) -> Self {
         let pb = ProgressBar::new_spinner();
         let mut options = SpinnerOptions::default();
         options.prefix = Some(prefix);
-        Self {
-            pb: Self::setup(pb, options),
-            is_finished: Arc::new(AtomicBool::new(false)),
-        }
+        Self::build(pb, options)
     }
 
     pub fn with_style(style: ProgressStyle) -> Self {
         let pb = ProgressBar::new_spinner();
         let mut options = SpinnerOptions::default();
         options.style = Some(style);
-        Self {
-            pb: Self::setup(pb, options),
-            is_finished: Arc::new(AtomicBool::new(false)),
-        }
+        Self::build(pb, options)
     }
 
     pub fn with_elapsed(elapsed: Duration) -> Self {
         let pb = ProgressBar::new_spinner().with_elapsed(elapsed);
-        Self {
-            pb: Self::setup(pb, SpinnerOptions::default()),
-            is_finished: Arc::new(AtomicBool::new(false)),
-        }
+        Self::build(pb, SpinnerOptions::default())
     }
 
     pub fn with_finish(finish: ProgressFinish) -> Self {
         let pb = ProgressBar::new_spinner().with_finish(finish);
-        Self {
-            pb: Self::setup(pb, SpinnerOptions::default()),
-            is_finished: Arc::new(AtomicBool::new(false)),
-        }
+        Self::build(pb, SpinnerOptions::default())
     }
 
     pub fn with_options(options: SpinnerOptions) -> Self {
         let pb = ProgressBar::new_spinner();
-        Self {
-            pb: Self::setup(pb, options),
-            is_finished: Arc::new(AtomicBool::new(false)),
-        }
+        Self::build(pb, options)
     }
 
     pub fn with(
@@ -116,8 +157,18 @@ impl Spinner {
         } else {
             ProgressBar::new_spinner()
         };
+        Self::build(pb, options)
+    }
+
+    fn build(pb: ProgressBar, options: SpinnerOptions) -> Self {
+        let styles = SpinnerStyles {
+            running: options.style.clone(),
+            success: options.success_style.clone(),
+            failure: options.failure_style.clone(),
+        };
         Self {
             pb: Self::setup(pb, options),
+            styles: Arc::new(styles),
             is_finished: Arc::new(AtomicBool::new(false)),
         }
     }
@@ -193,6 +244,50 @@ impl Spinner {
         Ok(result)
     }
 
+    pub async fn run_async<T, F, Fut>(&self, process: F, timeout: Option<Duration>) -> Result<T>
+    where
+        F: FnOnce(SpinnerHandle) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        if self.is_finished() {
+            return Err(InvalidOperationError(ERR_FINISHED.into()).into());
+        }
+
+        let handle = SpinnerHandle {
+            pb: self.pb.clone(),
+        };
+        // the steady tick enabled in setup keeps the spinner moving while the future runs
+        let future = process(handle);
+        let result = match timeout {
+            Some(timeout) => match time::timeout(timeout, future).await {
+                Ok(result) => result,
+                Err(_) => Err(TimedoutError.into()),
+            },
+            None => future.await,
+        };
+
+        self.is_finished.store(true, Ordering::Relaxed);
+
+        match &result {
+            Ok(_) => {
+                if let Some(style) = &self.styles.success {
+                    self.pb.set_style(style.clone());
+                }
+
+                self.pb.finish();
+            }
+            Err(e) => {
+                if let Some(style) = &self.styles.failure {
+                    self.pb.set_style(style.clone());
+                }
+
+                self.pb.abandon_with_message(e.to_string());
+            }
+        }
+
+        result
+    }
+
     pub fn suspend<F: FnOnce() -> R, R>(&self, f: F) -> R {
         self.pb.suspend(f)
     }
@@ -318,6 +413,10 @@ impl Spinner {
             self.pb.finish_and_clear();
         }
 
+        if let Some(style) = &self.styles.running {
+            self.pb.set_style(style.clone());
+        }
+
         self.pb.reset();
         self.is_finished.store(false, Ordering::Relaxed);
         Ok(())
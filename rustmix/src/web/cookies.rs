@@ -0,0 +1,205 @@
+use chrono::{DateTime, Utc};
+use cookie_store::{Cookie, CookieDomain, CookieExpiration, CookieStore as Store, RawCookie};
+use std::{
+    convert::Infallible,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+    sync::RwLock,
+};
+use url::Url;
+
+use super::reqwest::{cookie::CookieStore, header::HeaderValue};
+use crate::{
+    io::file::{self, write_atomic},
+    Result,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CookieEntry {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    pub path: String,
+    // host only cookies are sent to the exact host, the others to its subdomains too
+    pub host_only: bool,
+    pub secure: bool,
+    pub http_only: bool,
+    // None for session cookies
+    pub expires: Option<DateTime<Utc>>,
+}
+
+impl From<&Cookie<'_>> for CookieEntry {
+    fn from(cookie: &Cookie<'_>) -> Self {
+        let expires = match &cookie.expires {
+            CookieExpiration::AtUtc(time) => DateTime::from_timestamp(time.unix_timestamp(), 0),
+            CookieExpiration::SessionEnd => None,
+        };
+        Self {
+            name: cookie.name().to_string(),
+            value: cookie.value().to_string(),
+            domain: String::from(&cookie.domain),
+            path: String::from(&cookie.path),
+            host_only: matches!(cookie.domain, CookieDomain::HostOnly(_)),
+            secure: cookie.secure().unwrap_or_default(),
+            http_only: cookie.http_only().unwrap_or_default(),
+            expires,
+        }
+    }
+}
+
+// A cookie store that can be shared between clients and persisted to a json file
+#[derive(Debug, Default)]
+pub struct CookieJar {
+    store: RwLock<Store>,
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn load<T: AsRef<Path>>(path: T) -> Result<Self> {
+        let reader = BufReader::new(file::open(path)?);
+        let cookies: Vec<Cookie<'static>> = serde_json::from_reader(reader)?;
+        // expired cookies are dropped on the way in
+        let store = Store::from_cookies(cookies.into_iter().map(Ok::<_, Infallible>), false)
+            .unwrap_or_default();
+        Ok(Self {
+            store: RwLock::new(store),
+        })
+    }
+
+    pub fn load_or_new<T: AsRef<Path>>(path: T) -> Result<Self> {
+        let path = path.as_ref();
+
+        if !path.exists() {
+            return Ok(Self::new());
+        }
+
+        Self::load(path)
+    }
+
+    // session cookies are kept as well so a session survives a restart
+    pub fn save<T: AsRef<Path>>(&self, path: T) -> Result<()> {
+        let store = self.store.read().unwrap();
+        let cookies = store.iter_unexpired().collect::<Vec<_>>();
+        write_atomic(path, false, |file| {
+            let mut writer = BufWriter::new(file);
+            serde_json::to_writer_pretty(&mut writer, &cookies)?;
+            writer.flush()?;
+            Ok(())
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.read().unwrap().iter_unexpired().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn cookies(&self) -> Vec<CookieEntry> {
+        self.store
+            .read()
+            .unwrap()
+            .iter_unexpired()
+            .map(CookieEntry::from)
+            .collect()
+    }
+
+    pub fn cookies_for(&self, url: &Url) -> Vec<CookieEntry> {
+        self.store
+            .read()
+            .unwrap()
+            .matches(url)
+            .into_iter()
+            .map(CookieEntry::from)
+            .collect()
+    }
+
+    pub fn get<T: AsRef<str>>(&self, domain: T, path: T, name: T) -> Option<CookieEntry> {
+        self.store
+            .read()
+            .unwrap()
+            .get(domain.as_ref(), path.as_ref(), name.as_ref())
+            .map(CookieEntry::from)
+    }
+
+    // the value uses the Set-Cookie header format, i.e. "name=value; Path=/; Max-Age=3600"
+    pub fn insert<T: AsRef<str>>(&self, value: T, url: &Url) -> Result<()> {
+        self.store.write().unwrap().parse(value.as_ref(), url)?;
+        Ok(())
+    }
+
+    pub fn set<T: AsRef<str>>(&self, name: T, value: T, url: &Url) -> Result<()> {
+        let cookie = RawCookie::new(name.as_ref().to_string(), value.as_ref().to_string());
+        self.store.write().unwrap().insert_raw(&cookie, url)?;
+        Ok(())
+    }
+
+    pub fn remove<T: AsRef<str>>(&self, domain: T, path: T, name: T) -> Option<CookieEntry> {
+        self.store
+            .write()
+            .unwrap()
+            .remove(domain.as_ref(), path.as_ref(), name.as_ref())
+            .as_ref()
+            .map(CookieEntry::from)
+    }
+
+    pub fn retain<F: Fn(&CookieEntry) -> bool>(&self, filter: F) -> usize {
+        let mut store = self.store.write().unwrap();
+        let removed = store
+            .iter_any()
+            .filter(|e| !filter(&CookieEntry::from(*e)))
+            .map(|e| {
+                (
+                    String::from(&e.domain),
+                    String::from(&e.path),
+                    e.name().to_string(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        for (domain, path, name) in &removed {
+            store.remove(domain, path, name);
+        }
+
+        removed.len()
+    }
+
+    pub fn clear(&self) {
+        self.store.write().unwrap().clear();
+    }
+}
+
+impl CookieStore for CookieJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let cookies = cookie_headers.filter_map(|e| {
+            RawCookie::parse(e.to_str().ok()?)
+                .map(|e| e.into_owned())
+                .ok()
+        });
+        self.store
+            .write()
+            .unwrap()
+            .store_response_cookies(cookies, url);
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let value = self
+            .store
+            .read()
+            .unwrap()
+            .get_request_values(url)
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        if value.is_empty() {
+            return None;
+        }
+
+        HeaderValue::from_str(&value).ok()
+    }
+}
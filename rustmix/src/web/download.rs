@@ -0,0 +1,673 @@
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+use tokio::{
+    fs::OpenOptions,
+    io::{AsyncSeekExt, AsyncWriteExt},
+    time,
+};
+use url::Url;
+
+use super::reqwest::{build_client, header, Client, IntoUrl, Response, StatusCode};
+#[cfg(feature = "hash")]
+use crate::{
+    error::ChecksumMismatchError,
+    io::hash::{self, HashAlgorithm},
+};
+use crate::{
+    error::InvalidResponseError,
+    io::{directory, file},
+    threading::{HumanBytes, Spinner, SpinnerHandle},
+    Result,
+};
+
+const CHUNK_SIZE_DEF: u64 = 16 * 1024 * 1024;
+const MAX_PARALLEL_DEF: usize = 4;
+const MAX_RETRIES_DEF: usize = 3;
+const TIMEOUT_DEF: Duration = Duration::from_secs(60 * 60);
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+const PART_EXTENSION: &str = "part";
+const STATE_EXTENSION: &str = "part.json";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadOptions {
+    // files larger than this are split into chunks of this size
+    pub chunk_size: u64,
+    pub max_parallel: usize,
+    // per chunk, every retry resumes where the previous attempt stopped
+    pub max_retries: usize,
+    // per request, replaces the short timeout of the default client
+    pub timeout: Duration,
+    pub overwrite: bool,
+    pub progress: bool,
+    #[cfg(feature = "hash")]
+    pub checksum: Option<(HashAlgorithm, String)>,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        DownloadOptions {
+            chunk_size: CHUNK_SIZE_DEF,
+            max_parallel: MAX_PARALLEL_DEF,
+            max_retries: MAX_RETRIES_DEF,
+            timeout: TIMEOUT_DEF,
+            overwrite: false,
+            progress: true,
+            #[cfg(feature = "hash")]
+            checksum: None,
+        }
+    }
+}
+
+impl DownloadOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_chunk_size(&self, chunk_size: u64) -> Self {
+        DownloadOptions {
+            chunk_size,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_max_parallel(&self, max_parallel: usize) -> Self {
+        DownloadOptions {
+            max_parallel,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_max_retries(&self, max_retries: usize) -> Self {
+        DownloadOptions {
+            max_retries,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        DownloadOptions {
+            timeout,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_overwrite(&self, overwrite: bool) -> Self {
+        DownloadOptions {
+            overwrite,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_progress(&self, progress: bool) -> Self {
+        DownloadOptions {
+            progress,
+            ..self.clone()
+        }
+    }
+
+    #[cfg(feature = "hash")]
+    pub fn with_checksum<T: AsRef<str>>(&self, algorithm: HashAlgorithm, checksum: T) -> Self {
+        DownloadOptions {
+            checksum: Some((algorithm, checksum.as_ref().trim().to_lowercase())),
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DownloadReport {
+    pub url: String,
+    pub path: PathBuf,
+    pub size: u64,
+    // bytes transferred by this run, resumed bytes were already on disk
+    pub downloaded: u64,
+    pub resumed: u64,
+    pub chunks: usize,
+    pub elapsed: Duration,
+    pub checksum: Option<String>,
+    // the file existed and overwrite was off
+    pub skipped: bool,
+}
+
+impl fmt::Display for DownloadReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.skipped {
+            return write!(
+                f,
+                "{}: {} (exists)",
+                self.path.display(),
+                HumanBytes(self.size)
+            );
+        }
+
+        write!(
+            f,
+            "{}: {} in {:.1?}, {} chunk(s), {} resumed",
+            self.path.display(),
+            HumanBytes(self.size),
+            self.elapsed,
+            self.chunks,
+            HumanBytes(self.resumed)
+        )
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct RemoteFile {
+    size: Option<u64>,
+    ranges: bool,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Chunk {
+    start: u64,
+    // exclusive
+    end: u64,
+    downloaded: u64,
+}
+
+impl Chunk {
+    fn len(&self) -> u64 {
+        self.end - self.start
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct DownloadState {
+    url: String,
+    size: u64,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    chunks: Vec<Chunk>,
+}
+
+impl DownloadState {
+    fn matches(&self, url: &Url, remote: &RemoteFile) -> bool {
+        self.url == url.as_str()
+            && Some(self.size) == remote.size
+            && self.etag == remote.etag
+            && self.last_modified == remote.last_modified
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Downloader {
+    client: Client,
+    options: DownloadOptions,
+}
+
+impl Downloader {
+    pub fn new() -> Result<Self> {
+        Self::with_options(&DownloadOptions::new())
+    }
+
+    pub fn with_options(options: &DownloadOptions) -> Result<Self> {
+        let client = build_client().build()?;
+        Ok(Self::from_client(client, options))
+    }
+
+    pub fn from_client(client: Client, options: &DownloadOptions) -> Self {
+        Self {
+            client,
+            options: options.clone(),
+        }
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn options(&self) -> &DownloadOptions {
+        &self.options
+    }
+
+    pub async fn download<U: IntoUrl, P: AsRef<Path>>(
+        &self,
+        url: U,
+        path: P,
+    ) -> Result<DownloadReport> {
+        let url = url.into_url()?;
+        let path = path.as_ref().to_path_buf();
+
+        if path.is_file() && !self.options.overwrite {
+            return Ok(DownloadReport {
+                url: url.to_string(),
+                size: fs::metadata(&path)?.len(),
+                path,
+                downloaded: 0,
+                resumed: 0,
+                chunks: 0,
+                elapsed: Duration::ZERO,
+                checksum: None,
+                skipped: true,
+            });
+        }
+
+        if !self.options.progress {
+            return self.run(&url, &path, None).await;
+        }
+
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let spinner = Spinner::with_prefix(format!("{}: ", name));
+        spinner
+            .run_async(|handle| self.run(&url, &path, Some(handle)), None)
+            .await
+    }
+
+    async fn run(
+        &self,
+        url: &Url,
+        path: &Path,
+        handle: Option<SpinnerHandle>,
+    ) -> Result<DownloadReport> {
+        let start = Instant::now();
+        let part = sidecar(path, PART_EXTENSION);
+        let state_path = sidecar(path, STATE_EXTENSION);
+        // servers that do not answer HEAD are downloaded in one stream
+        let remote = self.probe(url).await.unwrap_or_default();
+
+        if let Some(dir) = path.parent().filter(|e| !e.as_os_str().is_empty()) {
+            directory::ensure(dir)?;
+        }
+
+        let (size, resumed, chunks) = match remote.size {
+            Some(size) if remote.ranges && size > 0 => {
+                let state = self.prepare(url, &remote, &part, &state_path)?;
+                let resumed = state.chunks.iter().map(|e| e.downloaded).sum::<u64>();
+                let chunks = state.chunks.len();
+                self.fetch_chunks(url, &part, &state_path, state, handle.as_ref())
+                    .await?;
+                (size, resumed, chunks)
+            }
+            _ => {
+                let size = self
+                    .fetch_stream(url, &part, remote.size, handle.as_ref())
+                    .await?;
+                (size, 0, 1)
+            }
+        };
+
+        #[cfg(feature = "hash")]
+        let checksum = self.verify(&part, &state_path, handle.as_ref()).await?;
+        #[cfg(not(feature = "hash"))]
+        let checksum = None;
+
+        // the target only shows up once it is complete
+        OpenOptions::new()
+            .write(true)
+            .open(&part)
+            .await?
+            .sync_all()
+            .await?;
+        fs::rename(&part, path)?;
+        file::sync_parent(path)?;
+
+        if state_path.exists() {
+            fs::remove_file(&state_path)?;
+        }
+
+        if let Some(handle) = &handle {
+            handle.set_message(format!("{} downloaded", HumanBytes(size)));
+        }
+
+        Ok(DownloadReport {
+            url: url.to_string(),
+            path: path.to_path_buf(),
+            size,
+            downloaded: size - resumed,
+            resumed,
+            chunks,
+            elapsed: start.elapsed(),
+            checksum,
+            skipped: false,
+        })
+    }
+
+    async fn probe(&self, url: &Url) -> Result<RemoteFile> {
+        let response = self
+            .client
+            .head(url.clone())
+            .timeout(self.options.timeout)
+            // ranges are offsets into the encoded body, so they only work without an encoding
+            .header(header::ACCEPT_ENCODING, "identity")
+            .send()
+            .await?
+            .error_for_status()?;
+        let headers = response.headers();
+        let value = |name: header::HeaderName| {
+            headers
+                .get(name)
+                .and_then(|e| e.to_str().ok())
+                .map(|e| e.to_string())
+        };
+        let encoded =
+            value(header::CONTENT_ENCODING).is_some_and(|e| !e.eq_ignore_ascii_case("identity"));
+        // the body of a HEAD response is empty, so the length comes from the header
+        Ok(RemoteFile {
+            size: value(header::CONTENT_LENGTH).and_then(|e| e.parse().ok()),
+            ranges: !encoded
+                && value(header::ACCEPT_RANGES).is_some_and(|e| e.eq_ignore_ascii_case("bytes")),
+            etag: value(header::ETAG),
+            last_modified: value(header::LAST_MODIFIED),
+        })
+    }
+
+    fn prepare(
+        &self,
+        url: &Url,
+        remote: &RemoteFile,
+        part: &Path,
+        state_path: &Path,
+    ) -> Result<DownloadState> {
+        let size = remote.size.unwrap_or_default();
+
+        if let Ok(state) = file::load::<DownloadState, _>(state_path) {
+            let on_disk = fs::metadata(part).map(|e| e.len()).unwrap_or_default();
+
+            if state.matches(url, remote) && on_disk == size {
+                return Ok(state);
+            }
+        }
+
+        let chunk_size = self.options.chunk_size.max(1);
+        let chunks = (0..size)
+            .step_by(chunk_size as usize)
+            .map(|start| Chunk {
+                start,
+                end: (start + chunk_size).min(size),
+                downloaded: 0,
+            })
+            .collect();
+        let state = DownloadState {
+            url: url.to_string(),
+            size,
+            etag: remote.etag.clone(),
+            last_modified: remote.last_modified.clone(),
+            chunks,
+        };
+        fs::File::create(part)?.set_len(size)?;
+        file::save(state_path, &state)?;
+        Ok(state)
+    }
+
+    async fn fetch_chunks(
+        &self,
+        url: &Url,
+        part: &Path,
+        state_path: &Path,
+        state: DownloadState,
+        handle: Option<&SpinnerHandle>,
+    ) -> Result<()> {
+        let progress = state
+            .chunks
+            .iter()
+            .map(|e| AtomicU64::new(e.downloaded))
+            .collect::<Vec<_>>();
+        let resumed = state.chunks.iter().map(|e| e.downloaded).sum::<u64>();
+        let start = Instant::now();
+        let result = {
+            let tasks = stream::iter(state.chunks.iter().zip(&progress))
+                .filter(|(chunk, _)| futures::future::ready(chunk.downloaded < chunk.len()))
+                .map(|(chunk, progress)| self.fetch_chunk(url, part, chunk, progress))
+                .buffer_unordered(self.options.max_parallel.max(1))
+                .try_collect::<Vec<_>>();
+            tokio::pin!(tasks);
+            let mut interval = time::interval(SAVE_INTERVAL);
+
+            loop {
+                tokio::select! {
+                    result = &mut tasks => break result,
+                    _ = interval.tick() => {
+                        let done = progress.iter().map(|e| e.load(Ordering::Relaxed)).sum::<u64>();
+                        report(handle, done, resumed, Some(state.size), start);
+                        save_state(state_path, snapshot(&state, &progress)).await?;
+                    }
+                }
+            }
+        };
+
+        // whatever happened, the next run picks up from here
+        save_state(state_path, snapshot(&state, &progress)).await?;
+        result.map(drop)
+    }
+
+    async fn fetch_chunk(
+        &self,
+        url: &Url,
+        part: &Path,
+        chunk: &Chunk,
+        progress: &AtomicU64,
+    ) -> Result<()> {
+        let mut tries = 0;
+
+        loop {
+            match self.fetch_range(url, part, chunk, progress).await {
+                Ok(()) => return Ok(()),
+                Err(_) if tries < self.options.max_retries => {
+                    tries += 1;
+                    time::sleep(RETRY_DELAY * tries as u32).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn fetch_range(
+        &self,
+        url: &Url,
+        part: &Path,
+        chunk: &Chunk,
+        progress: &AtomicU64,
+    ) -> Result<()> {
+        let offset = chunk.start + progress.load(Ordering::Relaxed);
+
+        if offset >= chunk.end {
+            return Ok(());
+        }
+
+        let response = self
+            .client
+            .get(url.clone())
+            .timeout(self.options.timeout)
+            .header(header::RANGE, format!("bytes={}-{}", offset, chunk.end - 1))
+            .header(header::ACCEPT_ENCODING, "identity")
+            .send()
+            .await?
+            .error_for_status()?;
+
+        // a full or encoded response would overwrite the other chunks
+        let encoded = response
+            .headers()
+            .get(header::CONTENT_ENCODING)
+            .is_some_and(|e| e != "identity");
+
+        if response.status() != StatusCode::PARTIAL_CONTENT || encoded {
+            return Err(InvalidResponseError.into());
+        }
+
+        let mut file = OpenOptions::new().write(true).open(part).await?;
+        file.seek(SeekFrom::Start(offset)).await?;
+        write_body(response, &mut file, progress, Some(chunk.end - offset)).await?;
+
+        if chunk.start + progress.load(Ordering::Relaxed) < chunk.end {
+            return Err(InvalidResponseError.into());
+        }
+
+        Ok(())
+    }
+
+    async fn fetch_stream(
+        &self,
+        url: &Url,
+        part: &Path,
+        size: Option<u64>,
+        handle: Option<&SpinnerHandle>,
+    ) -> Result<u64> {
+        let progress = AtomicU64::new(0);
+        let start = Instant::now();
+        let mut tries = 0;
+
+        loop {
+            // without range support every retry starts over
+            progress.store(0, Ordering::Relaxed);
+            let transfer = async {
+                let response = self
+                    .client
+                    .get(url.clone())
+                    .timeout(self.options.timeout)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+                let mut file = tokio::fs::File::create(part).await?;
+                write_body(response, &mut file, &progress, None).await
+            };
+            tokio::pin!(transfer);
+            let mut interval = time::interval(SAVE_INTERVAL);
+            let result = loop {
+                tokio::select! {
+                    result = &mut transfer => break result,
+                    _ = interval.tick() => report(handle, progress.load(Ordering::Relaxed), 0, size, start),
+                }
+            };
+
+            match result {
+                Ok(()) => return Ok(progress.load(Ordering::Relaxed)),
+                Err(_) if tries < self.options.max_retries => {
+                    tries += 1;
+                    time::sleep(RETRY_DELAY * tries as u32).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    #[cfg(feature = "hash")]
+    async fn verify(
+        &self,
+        part: &Path,
+        state_path: &Path,
+        handle: Option<&SpinnerHandle>,
+    ) -> Result<Option<String>> {
+        let Some((algorithm, expected)) = self.options.checksum.clone() else {
+            return Ok(None);
+        };
+
+        if let Some(handle) = handle {
+            handle.set_message(format!("verifying {}...", algorithm));
+        }
+
+        let path = part.to_path_buf();
+        let actual = tokio::task::spawn_blocking(move || {
+            hash::hash_file(path, algorithm).map_err(|e| e.to_string())
+        })
+        .await??;
+
+        if !actual.eq_ignore_ascii_case(&expected) {
+            // a corrupt file cannot be resumed into a good one
+            fs::remove_file(part)?;
+
+            if state_path.exists() {
+                fs::remove_file(state_path)?;
+            }
+
+            return Err(ChecksumMismatchError(expected, actual).into());
+        }
+
+        Ok(Some(actual))
+    }
+}
+
+pub async fn download<U: IntoUrl, P: AsRef<Path>>(url: U, path: P) -> Result<DownloadReport> {
+    Downloader::new()?.download(url, path).await
+}
+
+async fn write_body(
+    response: Response,
+    file: &mut tokio::fs::File,
+    progress: &AtomicU64,
+    limit: Option<u64>,
+) -> Result<()> {
+    let mut remaining = limit.unwrap_or(u64::MAX);
+    let mut body = response.bytes_stream();
+
+    while let Some(bytes) = body.next().await {
+        let bytes = bytes?;
+        let bytes = &bytes[..bytes.len().min(remaining as usize)];
+        file.write_all(bytes).await?;
+        // progress only counts what reached the file, the state file is saved from it
+        file.flush().await?;
+        progress.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        remaining -= bytes.len() as u64;
+
+        if remaining == 0 {
+            break;
+        }
+    }
+
+    file.flush().await?;
+    Ok(())
+}
+
+fn report(
+    handle: Option<&SpinnerHandle>,
+    done: u64,
+    resumed: u64,
+    size: Option<u64>,
+    start: Instant,
+) {
+    let Some(handle) = handle else {
+        return;
+    };
+    let elapsed = start.elapsed().as_secs_f64().max(0.001);
+    let rate = HumanBytes(((done - resumed) as f64 / elapsed) as u64);
+    let message = match size {
+        Some(size) if size > 0 => format!(
+            "{} / {} ({}%) {}/s",
+            HumanBytes(done),
+            HumanBytes(size),
+            done * 100 / size,
+            rate
+        ),
+        _ => format!("{} {}/s", HumanBytes(done), rate),
+    };
+    handle.set_message(message);
+}
+
+// keeps the file system off the runtime threads
+async fn save_state(path: &Path, state: DownloadState) -> Result<()> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || file::save(path, &state).map_err(|e| e.to_string()))
+        .await??;
+    Ok(())
+}
+
+fn snapshot(state: &DownloadState, progress: &[AtomicU64]) -> DownloadState {
+    let mut state = state.clone();
+    state
+        .chunks
+        .iter_mut()
+        .zip(progress)
+        .for_each(|(chunk, progress)| chunk.downloaded = progress.load(Ordering::Relaxed));
+    state
+}
+
+fn sidecar(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(extension);
+    path.with_file_name(name)
+}
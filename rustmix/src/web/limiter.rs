@@ -0,0 +1,460 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use super::{
+    host_key,
+    reqwest::{
+        blocking::{
+            Client as BlockingClient, Request as BlockingRequest,
+            RequestBuilder as BlockingRequestBuilder, Response as BlockingResponse,
+        },
+        build_blocking_client, build_client, Client, IntoUrl, Method, Request, RequestBuilder,
+        Response,
+    },
+};
+use crate::{random, Result};
+
+const REQUESTS_DEF: u32 = 2;
+const PERIOD_DEF: Duration = Duration::from_secs(1);
+const MAX_CONCURRENT_DEF: usize = 4;
+const JITTER_DEF: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimitOptions {
+    // requests per period are spaced evenly, 0 disables the rate limit
+    pub requests: u32,
+    pub period: Duration,
+    // requests in flight per host, 0 disables the cap
+    pub max_concurrent: usize,
+    // up to this much is randomly added to every delay
+    pub jitter: Duration,
+}
+
+impl Default for RateLimitOptions {
+    fn default() -> Self {
+        RateLimitOptions {
+            requests: REQUESTS_DEF,
+            period: PERIOD_DEF,
+            max_concurrent: MAX_CONCURRENT_DEF,
+            jitter: JITTER_DEF,
+        }
+    }
+}
+
+impl RateLimitOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_rate(&self, requests: u32, period: Duration) -> Self {
+        RateLimitOptions {
+            requests,
+            period,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_max_concurrent(&self, max_concurrent: usize) -> Self {
+        RateLimitOptions {
+            max_concurrent,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_jitter(&self, jitter: Duration) -> Self {
+        RateLimitOptions {
+            jitter,
+            ..self.clone()
+        }
+    }
+
+    fn interval(&self) -> Duration {
+        if self.requests == 0 {
+            return Duration::ZERO;
+        }
+
+        self.period / self.requests
+    }
+
+    fn jitter(&self) -> Duration {
+        if self.jitter.is_zero() {
+            return Duration::ZERO;
+        }
+
+        // a non-zero jitter is at least a nanosecond, so the range is never empty
+        let nanos = self.jitter.as_nanos().min(u64::MAX as u128) as u64;
+        Duration::from_nanos(random::numeric(0..nanos))
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RateLimitMetrics {
+    pub requests: u64,
+    // requests that had to wait for a slot
+    pub delayed: u64,
+    pub total_wait: Duration,
+    pub max_wait: Duration,
+    pub active: usize,
+}
+
+impl RateLimitMetrics {
+    pub fn average_wait(&self) -> Duration {
+        if self.requests == 0 {
+            return Duration::ZERO;
+        }
+
+        self.total_wait / self.requests as u32
+    }
+
+    fn merge(&mut self, other: &RateLimitMetrics) {
+        self.requests += other.requests;
+        self.delayed += other.delayed;
+        self.total_wait += other.total_wait;
+        self.max_wait = self.max_wait.max(other.max_wait);
+        self.active += other.active;
+    }
+}
+
+impl fmt::Display for RateLimitMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} requests, {} delayed, {:?} average wait, {:?} max wait, {} active",
+            self.requests,
+            self.delayed,
+            self.average_wait(),
+            self.max_wait,
+            self.active
+        )
+    }
+}
+
+#[derive(Debug)]
+struct HostState {
+    next: Instant,
+    metrics: RateLimitMetrics,
+}
+
+#[derive(Debug)]
+struct HostLimiter {
+    options: RateLimitOptions,
+    semaphore: Option<Arc<Semaphore>>,
+    state: Mutex<HostState>,
+}
+
+impl HostLimiter {
+    fn new(options: &RateLimitOptions) -> Self {
+        let semaphore = match options.max_concurrent {
+            0 => None,
+            n => Some(Arc::new(Semaphore::new(n))),
+        };
+        Self {
+            options: options.clone(),
+            semaphore,
+            state: Mutex::new(HostState {
+                next: Instant::now(),
+                metrics: RateLimitMetrics::default(),
+            }),
+        }
+    }
+
+    // takes the next free slot, the caller waits for the returned delay
+    fn reserve(&self) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let at = state.next.max(now);
+        state.next = at + self.options.interval() + self.options.jitter();
+        at - now
+    }
+
+    fn record(&self, waited: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let metrics = &mut state.metrics;
+        metrics.requests += 1;
+        metrics.active += 1;
+        metrics.total_wait += waited;
+        metrics.max_wait = metrics.max_wait.max(waited);
+
+        // semaphore hand-offs and timer resolution are not worth reporting
+        if waited >= Duration::from_millis(1) {
+            metrics.delayed += 1;
+        }
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.metrics.active = state.metrics.active.saturating_sub(1);
+    }
+}
+
+#[derive(Debug)]
+pub struct RatePermit {
+    host: Arc<HostLimiter>,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for RatePermit {
+    fn drop(&mut self) {
+        self.host.release();
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    options: RateLimitOptions,
+    overrides: Mutex<HashMap<String, RateLimitOptions>>,
+    hosts: Mutex<HashMap<String, Arc<HostLimiter>>>,
+}
+
+impl RateLimiter {
+    pub fn new(options: &RateLimitOptions) -> Self {
+        Self {
+            options: options.clone(),
+            overrides: Mutex::new(HashMap::new()),
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn options(&self) -> &RateLimitOptions {
+        &self.options
+    }
+
+    // hosts are keyed as host:port, e.g. example.com:443
+    pub fn set_host_options<T: AsRef<str>>(&self, host: T, options: &RateLimitOptions) {
+        let host = host.as_ref();
+        self.overrides
+            .lock()
+            .unwrap()
+            .insert(host.to_string(), options.clone());
+        // requests in flight keep their permits from the old limits
+        self.hosts.lock().unwrap().remove(host);
+    }
+
+    pub async fn acquire<T: AsRef<str>>(&self, host: T) -> RatePermit {
+        let start = Instant::now();
+        let host = self.host(host.as_ref());
+        let permit = match &host.semaphore {
+            // the semaphore is never closed
+            Some(semaphore) => Some(semaphore.clone().acquire_owned().await.unwrap()),
+            None => None,
+        };
+        let delay = host.reserve();
+
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+
+        host.record(start.elapsed());
+        RatePermit {
+            host,
+            _permit: permit,
+        }
+    }
+
+    pub fn acquire_blocking<T: AsRef<str>>(&self, host: T) -> RatePermit {
+        let start = Instant::now();
+        let host = self.host(host.as_ref());
+        let permit = host
+            .semaphore
+            .as_ref()
+            .map(|e| futures::executor::block_on(e.clone().acquire_owned()).unwrap());
+        let delay = host.reserve();
+
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+
+        host.record(start.elapsed());
+        RatePermit {
+            host,
+            _permit: permit,
+        }
+    }
+
+    pub fn metrics<T: AsRef<str>>(&self, host: T) -> RateLimitMetrics {
+        let hosts = self.hosts.lock().unwrap();
+        hosts
+            .get(host.as_ref())
+            .map(|e| e.state.lock().unwrap().metrics.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn metrics_all(&self) -> HashMap<String, RateLimitMetrics> {
+        let hosts = self.hosts.lock().unwrap();
+        hosts
+            .iter()
+            .map(|(k, v)| (k.clone(), v.state.lock().unwrap().metrics.clone()))
+            .collect()
+    }
+
+    pub fn total(&self) -> RateLimitMetrics {
+        let mut total = RateLimitMetrics::default();
+
+        for metrics in self.metrics_all().values() {
+            total.merge(metrics);
+        }
+
+        total
+    }
+
+    fn host(&self, host: &str) -> Arc<HostLimiter> {
+        let mut hosts = self.hosts.lock().unwrap();
+
+        if let Some(limiter) = hosts.get(host) {
+            return limiter.clone();
+        }
+
+        let overrides = self.overrides.lock().unwrap();
+        let options = overrides.get(host).unwrap_or(&self.options);
+        let limiter = Arc::new(HostLimiter::new(options));
+        hosts.insert(host.to_string(), limiter.clone());
+        limiter
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(&RateLimitOptions::default())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitedClient {
+    client: Client,
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimitedClient {
+    pub fn new() -> Result<Self> {
+        Self::with_options(&RateLimitOptions::new())
+    }
+
+    pub fn with_options(options: &RateLimitOptions) -> Result<Self> {
+        let limiter = Arc::new(RateLimiter::new(options));
+        Ok(Self::from_client(build_client().build()?, &limiter))
+    }
+
+    // clients sharing a limiter share the per-host limits
+    pub fn from_client(client: Client, limiter: &Arc<RateLimiter>) -> Self {
+        Self {
+            client,
+            limiter: limiter.clone(),
+        }
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn limiter(&self) -> &Arc<RateLimiter> {
+        &self.limiter
+    }
+
+    pub fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    pub fn head<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.client.head(url)
+    }
+
+    pub fn post<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.client.post(url)
+    }
+
+    pub fn put<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.client.put(url)
+    }
+
+    pub fn delete<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.client.delete(url)
+    }
+
+    pub fn request<U: IntoUrl>(&self, method: Method, url: U) -> RequestBuilder {
+        self.client.request(method, url)
+    }
+
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        self.execute(request.build()?).await
+    }
+
+    // the slot is held until the response headers arrive
+    pub async fn execute(&self, request: Request) -> Result<Response> {
+        let _permit = self.limiter.acquire(host_key(request.url())).await;
+        self.client.execute(request).await.map_err(Into::into)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockingRateLimitedClient {
+    client: BlockingClient,
+    limiter: Arc<RateLimiter>,
+}
+
+impl BlockingRateLimitedClient {
+    pub fn new() -> Result<Self> {
+        Self::with_options(&RateLimitOptions::new())
+    }
+
+    pub fn with_options(options: &RateLimitOptions) -> Result<Self> {
+        let limiter = Arc::new(RateLimiter::new(options));
+        Ok(Self::from_client(
+            build_blocking_client().build()?,
+            &limiter,
+        ))
+    }
+
+    pub fn from_client(client: BlockingClient, limiter: &Arc<RateLimiter>) -> Self {
+        Self {
+            client,
+            limiter: limiter.clone(),
+        }
+    }
+
+    pub fn client(&self) -> &BlockingClient {
+        &self.client
+    }
+
+    pub fn limiter(&self) -> &Arc<RateLimiter> {
+        &self.limiter
+    }
+
+    pub fn get<U: IntoUrl>(&self, url: U) -> BlockingRequestBuilder {
+        self.client.get(url)
+    }
+
+    pub fn head<U: IntoUrl>(&self, url: U) -> BlockingRequestBuilder {
+        self.client.head(url)
+    }
+
+    pub fn post<U: IntoUrl>(&self, url: U) -> BlockingRequestBuilder {
+        self.client.post(url)
+    }
+
+    pub fn put<U: IntoUrl>(&self, url: U) -> BlockingRequestBuilder {
+        self.client.put(url)
+    }
+
+    pub fn delete<U: IntoUrl>(&self, url: U) -> BlockingRequestBuilder {
+        self.client.delete(url)
+    }
+
+    pub fn request<U: IntoUrl>(&self, method: Method, url: U) -> BlockingRequestBuilder {
+        self.client.request(method, url)
+    }
+
+    pub fn send(&self, request: BlockingRequestBuilder) -> Result<BlockingResponse> {
+        self.execute(request.build()?)
+    }
+
+    pub fn execute(&self, request: BlockingRequest) -> Result<BlockingResponse> {
+        let _permit = self.limiter.acquire_blocking(host_key(request.url()));
+        self.client.execute(request).map_err(Into::into)
+    }
+}
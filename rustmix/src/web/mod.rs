@@ -1,6 +1,12 @@
 #[cfg(feature = "mail")]
 pub mod mail;
+pub mod cookies;
+pub mod download;
+pub mod limiter;
+pub mod profile;
+pub mod proxy;
 pub mod reqwest;
+pub mod resilient;
 
 use url::{ParseError, Url};
 use urlencoding::{decode, encode};
@@ -110,6 +116,15 @@ impl<T: AsRef<str>> AsUrl<T> for Vec<T> {
     }
 }
 
+// identifies a host for per-host limits, e.g. example.com:443
+pub(crate) fn host_key(url: &Url) -> String {
+    format!(
+        "{}:{}",
+        url.host_str().unwrap_or_default(),
+        url.port_or_known_default().unwrap_or_default()
+    )
+}
+
 pub fn remove<T: AsRef<str>>(url: &mut Url, value: T) {
     let value = value.as_ref();
 
@@ -0,0 +1,430 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, sync::Mutex};
+
+use super::reqwest::{blocking::RequestBuilder as BlockingRequestBuilder, header, RequestBuilder};
+use crate::random;
+
+const CHROME_VERSIONS: (u32, u32) = (124, 131);
+const FIREFOX_VERSIONS: (u32, u32) = (125, 133);
+const SAFARI_VERSIONS: (u32, u32) = (16, 18);
+const LANGUAGE_DEF: &str = "en-US";
+
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub enum Browser {
+    #[default]
+    Chrome,
+    Edge,
+    Firefox,
+    Safari,
+}
+
+impl Browser {
+    pub fn platforms(&self) -> &'static [Platform] {
+        match self {
+            Browser::Chrome | Browser::Firefox => &[
+                Platform::Windows,
+                Platform::MacOS,
+                Platform::Linux,
+                Platform::Android,
+            ],
+            Browser::Edge => &[Platform::Windows, Platform::MacOS],
+            Browser::Safari => &[Platform::MacOS, Platform::IOS],
+        }
+    }
+
+    pub fn is_chromium(&self) -> bool {
+        matches!(self, Browser::Chrome | Browser::Edge)
+    }
+}
+
+impl fmt::Display for Browser {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Browser::Chrome => write!(f, "Chrome"),
+            Browser::Edge => write!(f, "Edge"),
+            Browser::Firefox => write!(f, "Firefox"),
+            Browser::Safari => write!(f, "Safari"),
+        }
+    }
+}
+
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub enum Platform {
+    #[default]
+    Windows,
+    MacOS,
+    Linux,
+    Android,
+    IOS,
+}
+
+impl Platform {
+    pub fn is_mobile(&self) -> bool {
+        matches!(self, Platform::Android | Platform::IOS)
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Platform::Windows => write!(f, "Windows"),
+            Platform::MacOS => write!(f, "macOS"),
+            Platform::Linux => write!(f, "Linux"),
+            Platform::Android => write!(f, "Android"),
+            Platform::IOS => write!(f, "iOS"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RotationMode {
+    PerRequest,
+    #[default]
+    PerSession,
+}
+
+impl fmt::Display for RotationMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RotationMode::PerRequest => write!(f, "PerRequest"),
+            RotationMode::PerSession => write!(f, "PerSession"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BrowserProfile {
+    pub browser: Browser,
+    pub platform: Platform,
+    pub version: u32,
+    pub minor: u32,
+    pub user_agent: String,
+    pub accept: String,
+    pub accept_language: String,
+    // client hints, only chromium browsers send them
+    pub sec_ch_ua: Option<String>,
+    pub sec_ch_ua_mobile: Option<String>,
+    pub sec_ch_ua_platform: Option<String>,
+}
+
+impl BrowserProfile {
+    // a platform the browser does not run on is replaced with one it does
+    pub fn new<T: AsRef<str>>(browser: Browser, platform: Platform, language: T) -> Self {
+        let platform = match browser.platforms().contains(&platform) {
+            true => platform,
+            false => browser.platforms()[0],
+        };
+        let (version, minor) = match browser {
+            Browser::Chrome | Browser::Edge => (pick_version(CHROME_VERSIONS), 0),
+            Browser::Firefox => (pick_version(FIREFOX_VERSIONS), 0),
+            Browser::Safari => (pick_version(SAFARI_VERSIONS), random::numeric(0..7u32)),
+        };
+        Self::with_version(browser, platform, version, minor, language)
+    }
+
+    pub fn with_version<T: AsRef<str>>(
+        browser: Browser,
+        platform: Platform,
+        version: u32,
+        minor: u32,
+        language: T,
+    ) -> Self {
+        let language = language.as_ref();
+        let user_agent = user_agent(browser, platform, version, minor);
+        let (accept, quality) = match browser {
+            Browser::Chrome | Browser::Edge => ("text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,image/apng,*/*;q=0.8,application/signed-exchange;v=b3;q=0.7", "0.9"),
+            Browser::Firefox => ("text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,*/*;q=0.8", "0.5"),
+            Browser::Safari => ("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8", "0.9"),
+        };
+        let accept_language = match language.split_once('-') {
+            Some((primary, _)) => format!("{},{};q={}", language, primary, quality),
+            None => language.to_string(),
+        };
+        let (sec_ch_ua, sec_ch_ua_mobile, sec_ch_ua_platform) = match browser.is_chromium() {
+            true => {
+                let brand = match browser {
+                    Browser::Edge => "Microsoft Edge",
+                    _ => "Google Chrome",
+                };
+                (
+                    Some(format!(
+                        "\"{}\";v=\"{}\", \"Chromium\";v=\"{}\", \"Not_A Brand\";v=\"24\"",
+                        brand, version, version
+                    )),
+                    Some(if platform.is_mobile() { "?1" } else { "?0" }.to_string()),
+                    Some(format!("\"{}\"", platform)),
+                )
+            }
+            false => (None, None, None),
+        };
+        Self {
+            browser,
+            platform,
+            version,
+            minor,
+            user_agent,
+            accept: accept.to_string(),
+            accept_language,
+            sec_ch_ua,
+            sec_ch_ua_mobile,
+            sec_ch_ua_platform,
+        }
+    }
+
+    pub fn random() -> Self {
+        ProfileOptions::new().create()
+    }
+
+    pub fn headers(&self) -> header::HeaderMap {
+        let mut headers = header::HeaderMap::new();
+        let mut insert = |name: header::HeaderName, value: &str| {
+            // the values are built from ascii templates, the language is the only input
+            if let Ok(value) = header::HeaderValue::from_str(value) {
+                headers.insert(name, value);
+            }
+        };
+        insert(header::USER_AGENT, &self.user_agent);
+        insert(header::ACCEPT, &self.accept);
+        insert(header::ACCEPT_LANGUAGE, &self.accept_language);
+        insert(header::UPGRADE_INSECURE_REQUESTS, "1");
+
+        if let Some(value) = &self.sec_ch_ua {
+            insert(header::HeaderName::from_static("sec-ch-ua"), value);
+        }
+
+        if let Some(value) = &self.sec_ch_ua_mobile {
+            insert(header::HeaderName::from_static("sec-ch-ua-mobile"), value);
+        }
+
+        if let Some(value) = &self.sec_ch_ua_platform {
+            insert(header::HeaderName::from_static("sec-ch-ua-platform"), value);
+        }
+
+        headers
+    }
+}
+
+impl fmt::Display for BrowserProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.user_agent)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileOptions {
+    pub browsers: Vec<(Browser, u32)>,
+    pub platforms: Vec<(Platform, u32)>,
+    pub languages: Vec<(String, u32)>,
+    pub rotation: RotationMode,
+}
+
+impl Default for ProfileOptions {
+    fn default() -> Self {
+        ProfileOptions {
+            browsers: vec![
+                (Browser::Chrome, 65),
+                (Browser::Safari, 18),
+                (Browser::Edge, 10),
+                (Browser::Firefox, 7),
+            ],
+            platforms: vec![
+                (Platform::Windows, 45),
+                (Platform::MacOS, 20),
+                (Platform::Android, 20),
+                (Platform::IOS, 10),
+                (Platform::Linux, 5),
+            ],
+            languages: vec![(LANGUAGE_DEF.to_string(), 1)],
+            rotation: RotationMode::default(),
+        }
+    }
+}
+
+impl ProfileOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_browsers(&self, browsers: &[(Browser, u32)]) -> Self {
+        ProfileOptions {
+            browsers: browsers.to_vec(),
+            ..self.clone()
+        }
+    }
+
+    pub fn with_platforms(&self, platforms: &[(Platform, u32)]) -> Self {
+        ProfileOptions {
+            platforms: platforms.to_vec(),
+            ..self.clone()
+        }
+    }
+
+    pub fn with_languages<T: AsRef<str>>(&self, languages: &[(T, u32)]) -> Self {
+        ProfileOptions {
+            languages: languages
+                .iter()
+                .map(|(language, weight)| (language.as_ref().to_string(), *weight))
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    pub fn with_rotation(&self, rotation: RotationMode) -> Self {
+        ProfileOptions {
+            rotation,
+            ..self.clone()
+        }
+    }
+
+    pub fn create(&self) -> BrowserProfile {
+        let browser = pick_weighted(&self.browsers).unwrap_or_default();
+        // only the platforms the browser runs on are considered
+        let platforms = self
+            .platforms
+            .iter()
+            .filter(|(platform, _)| browser.platforms().contains(platform))
+            .cloned()
+            .collect::<Vec<_>>();
+        let platform = pick_weighted(&platforms).unwrap_or(browser.platforms()[0]);
+        let language = pick_weighted(&self.languages).unwrap_or_else(|| LANGUAGE_DEF.to_string());
+        BrowserProfile::new(browser, platform, language)
+    }
+}
+
+#[derive(Debug)]
+pub struct HeaderRotator {
+    options: ProfileOptions,
+    current: Mutex<BrowserProfile>,
+}
+
+impl HeaderRotator {
+    pub fn new() -> Self {
+        Self::with_options(&ProfileOptions::new())
+    }
+
+    pub fn with_options(options: &ProfileOptions) -> Self {
+        Self {
+            options: options.clone(),
+            current: Mutex::new(options.create()),
+        }
+    }
+
+    pub fn options(&self) -> &ProfileOptions {
+        &self.options
+    }
+
+    pub fn current(&self) -> BrowserProfile {
+        self.current.lock().unwrap().clone()
+    }
+
+    // starts a new session, the next requests use the returned profile
+    pub fn rotate(&self) -> BrowserProfile {
+        let profile = self.options.create();
+        *self.current.lock().unwrap() = profile.clone();
+        profile
+    }
+
+    pub fn next(&self) -> BrowserProfile {
+        match self.options.rotation {
+            RotationMode::PerRequest => self.rotate(),
+            RotationMode::PerSession => self.current(),
+        }
+    }
+
+    pub fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        request.headers(self.next().headers())
+    }
+
+    pub fn apply_blocking(&self, request: BlockingRequestBuilder) -> BlockingRequestBuilder {
+        request.headers(self.next().headers())
+    }
+}
+
+impl Default for HeaderRotator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn pick_version(range: (u32, u32)) -> u32 {
+    random::numeric(range.0..range.1 + 1)
+}
+
+fn pick_weighted<T: Clone>(items: &[(T, u32)]) -> Option<T> {
+    let total = items.iter().map(|(_, weight)| *weight as u64).sum::<u64>();
+
+    if total == 0 {
+        return items.first().map(|(item, _)| item.clone());
+    }
+
+    let mut n = random::numeric(0..total);
+
+    for (item, weight) in items {
+        let weight = *weight as u64;
+
+        if n < weight {
+            return Some(item.clone());
+        }
+
+        n -= weight;
+    }
+
+    None
+}
+
+fn user_agent(browser: Browser, platform: Platform, version: u32, minor: u32) -> String {
+    const WEBKIT: &str = "AppleWebKit/537.36 (KHTML, like Gecko)";
+
+    match browser {
+        Browser::Chrome | Browser::Edge => {
+            let system = match platform {
+                Platform::MacOS => "Macintosh; Intel Mac OS X 10_15_7",
+                Platform::Linux => "X11; Linux x86_64",
+                Platform::Android => "Linux; Android 10; K",
+                _ => "Windows NT 10.0; Win64; x64",
+            };
+            let mobile = if platform.is_mobile() { "Mobile " } else { "" };
+            let edge = match browser {
+                Browser::Edge => format!(" Edg/{}.0.0.0", version),
+                _ => String::new(),
+            };
+            format!(
+                "Mozilla/5.0 ({}) {} Chrome/{}.0.0.0 {}Safari/537.36{}",
+                system, WEBKIT, version, mobile, edge
+            )
+        }
+        Browser::Firefox => match platform {
+            Platform::Android => format!(
+                "Mozilla/5.0 (Android 14; Mobile; rv:{v}.0) Gecko/{v}.0 Firefox/{v}.0",
+                v = version
+            ),
+            _ => {
+                let system = match platform {
+                    Platform::MacOS => "Macintosh; Intel Mac OS X 10.15",
+                    Platform::Linux => "X11; Linux x86_64",
+                    _ => "Windows NT 10.0; Win64; x64",
+                };
+                format!(
+                    "Mozilla/5.0 ({}; rv:{v}.0) Gecko/20100101 Firefox/{v}.0",
+                    system,
+                    v = version
+                )
+            }
+        },
+        Browser::Safari => match platform {
+            Platform::IOS => format!(
+                "Mozilla/5.0 (iPhone; CPU iPhone OS {v}_{m} like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/{v}.{m} Mobile/15E148 Safari/604.1",
+                v = version,
+                m = minor
+            ),
+            _ => format!(
+                "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/{}.{} Safari/605.1.15",
+                version, minor
+            ),
+        },
+    }
+}
@@ -0,0 +1,478 @@
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use url::Url;
+
+use super::{
+    profile::RotationMode,
+    reqwest::{
+        blocking::Client as BlockingClient, build_blocking_client, build_client, Client, Proxy,
+    },
+    REMOTE_IP_URL,
+};
+use crate::{
+    error::{InvalidInputError, NotSupportedError},
+    io::file::{self, FileEx},
+    Result,
+};
+
+const CHECK_TIMEOUT_DEF: Duration = Duration::from_secs(10);
+const CHECK_CONCURRENCY_DEF: usize = 16;
+const MAX_FAILURES_DEF: u32 = 3;
+const SCHEMES: [&str; 5] = ["http", "https", "socks4", "socks5", "socks5h"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyOptions {
+    pub check_url: String,
+    pub check_timeout: Duration,
+    // proxies checked at the same time
+    pub check_concurrency: usize,
+    // consecutive failures before a proxy is evicted, 0 never evicts
+    pub max_failures: u32,
+    pub rotation: RotationMode,
+}
+
+impl Default for ProxyOptions {
+    fn default() -> Self {
+        ProxyOptions {
+            check_url: REMOTE_IP_URL.to_string(),
+            check_timeout: CHECK_TIMEOUT_DEF,
+            check_concurrency: CHECK_CONCURRENCY_DEF,
+            max_failures: MAX_FAILURES_DEF,
+            rotation: RotationMode::default(),
+        }
+    }
+}
+
+impl ProxyOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_check_url<T: AsRef<str>>(&self, check_url: T) -> Self {
+        ProxyOptions {
+            check_url: check_url.as_ref().to_string(),
+            ..self.clone()
+        }
+    }
+
+    pub fn with_check_timeout(&self, check_timeout: Duration) -> Self {
+        ProxyOptions {
+            check_timeout,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_check_concurrency(&self, check_concurrency: usize) -> Self {
+        ProxyOptions {
+            check_concurrency,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_max_failures(&self, max_failures: u32) -> Self {
+        ProxyOptions {
+            max_failures,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_rotation(&self, rotation: RotationMode) -> Self {
+        ProxyOptions {
+            rotation,
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProxyEntry {
+    pub url: Url,
+    pub successes: u64,
+    pub failures: u64,
+    pub consecutive_failures: u32,
+    pub latency: Option<Duration>,
+    // the address the check url reported for the last successful check
+    pub address: Option<String>,
+}
+
+impl ProxyEntry {
+    // host:port without a scheme is taken as an http proxy
+    pub fn parse<T: AsRef<str>>(value: T) -> Result<Self> {
+        let value = value.as_ref().trim();
+
+        if value.is_empty() {
+            return Err(InvalidInputError.into());
+        }
+
+        let url = match value.contains("://") {
+            true => Url::parse(value)?,
+            false => Url::parse(&format!("http://{}", value))?,
+        };
+
+        if !SCHEMES.contains(&url.scheme()) {
+            return Err(NotSupportedError.into());
+        }
+
+        if url.host_str().is_none() {
+            return Err(InvalidInputError.into());
+        }
+
+        Ok(Self {
+            url,
+            successes: 0,
+            failures: 0,
+            consecutive_failures: 0,
+            latency: None,
+            address: None,
+        })
+    }
+
+    pub fn is_socks(&self) -> bool {
+        self.url.scheme().starts_with("socks")
+    }
+
+    pub fn proxy(&self) -> Result<Proxy> {
+        Ok(Proxy::all(self.url.as_str())?)
+    }
+
+    pub fn build_client(&self) -> Result<Client> {
+        Ok(build_client().proxy(self.proxy()?).build()?)
+    }
+
+    pub fn build_blocking_client(&self) -> Result<BlockingClient> {
+        Ok(build_blocking_client().proxy(self.proxy()?).build()?)
+    }
+}
+
+impl fmt::Display for ProxyEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", redact(&self.url))
+    }
+}
+
+impl fmt::Debug for ProxyEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ProxyEntry")
+            .field("url", &redact(&self.url).as_str())
+            .field("successes", &self.successes)
+            .field("failures", &self.failures)
+            .field("consecutive_failures", &self.consecutive_failures)
+            .field("latency", &self.latency)
+            .field("address", &self.address)
+            .finish()
+    }
+}
+
+#[derive(Default)]
+struct PoolState {
+    proxies: Vec<ProxyEntry>,
+    cursor: usize,
+    session: Option<Url>,
+}
+
+impl fmt::Debug for PoolState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PoolState")
+            .field("proxies", &self.proxies)
+            .field("cursor", &self.cursor)
+            .field(
+                "session",
+                &self.session.as_ref().map(|e| redact(e).to_string()),
+            )
+            .finish()
+    }
+}
+
+impl PoolState {
+    // returns true if the proxy was evicted
+    fn record_failure(&mut self, index: usize, max_failures: u32) -> bool {
+        let entry = &mut self.proxies[index];
+        entry.failures += 1;
+        entry.consecutive_failures += 1;
+
+        if max_failures == 0 || entry.consecutive_failures < max_failures {
+            return false;
+        }
+
+        let entry = self.proxies.remove(index);
+
+        if self.session.as_ref() == Some(&entry.url) {
+            self.session = None;
+        }
+
+        true
+    }
+}
+
+#[derive(Debug)]
+pub struct ProxyPool {
+    options: ProxyOptions,
+    state: Mutex<PoolState>,
+}
+
+impl ProxyPool {
+    pub fn new() -> Self {
+        Self::with_options(&ProxyOptions::new())
+    }
+
+    pub fn with_options(options: &ProxyOptions) -> Self {
+        Self {
+            options: options.clone(),
+            state: Mutex::new(PoolState::default()),
+        }
+    }
+
+    pub fn from_list<T: AsRef<str>>(proxies: &[T], options: &ProxyOptions) -> Result<Self> {
+        let pool = Self::with_options(options);
+
+        for proxy in proxies {
+            pool.add(proxy)?;
+        }
+
+        Ok(pool)
+    }
+
+    // one proxy per line, blank lines and lines starting with # are skipped
+    pub fn from_file<T: AsRef<Path>>(path: T, options: &ProxyOptions) -> Result<Self> {
+        let lines = file::open(path)?
+            .read_filtered(|e| {
+                let e = e.trim();
+                !e.is_empty() && !e.starts_with('#')
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        Self::from_list(&lines, options)
+    }
+
+    pub fn options(&self) -> &ProxyOptions {
+        &self.options
+    }
+
+    pub fn add<T: AsRef<str>>(&self, proxy: T) -> Result<bool> {
+        let entry = ProxyEntry::parse(proxy)?;
+        let mut state = self.state.lock().unwrap();
+
+        if state.proxies.iter().any(|e| e.url == entry.url) {
+            return Ok(false);
+        }
+
+        state.proxies.push(entry);
+        Ok(true)
+    }
+
+    pub fn remove(&self, url: &Url) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let len = state.proxies.len();
+        state.proxies.retain(|e| &e.url != url);
+
+        if state.session.as_ref() == Some(url) {
+            state.session = None;
+        }
+
+        len != state.proxies.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().proxies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn proxies(&self) -> Vec<ProxyEntry> {
+        self.state.lock().unwrap().proxies.clone()
+    }
+
+    pub fn next(&self) -> Option<ProxyEntry> {
+        let mut state = self.state.lock().unwrap();
+
+        if self.options.rotation == RotationMode::PerSession {
+            if let Some(url) = &state.session {
+                if let Some(entry) = state.proxies.iter().find(|e| &e.url == url) {
+                    return Some(entry.clone());
+                }
+            }
+        }
+
+        if state.proxies.is_empty() {
+            return None;
+        }
+
+        let i = state.cursor % state.proxies.len();
+        state.cursor = i + 1;
+        let entry = state.proxies[i].clone();
+        state.session = Some(entry.url.clone());
+        Some(entry)
+    }
+
+    // ends the sticky session, the next call picks another proxy
+    pub fn rotate(&self) -> Option<ProxyEntry> {
+        self.state.lock().unwrap().session = None;
+        self.next()
+    }
+
+    pub fn client(&self) -> Result<Option<(ProxyEntry, Client)>> {
+        let Some(entry) = self.next() else {
+            return Ok(None);
+        };
+        let client = entry.build_client()?;
+        Ok(Some((entry, client)))
+    }
+
+    pub fn blocking_client(&self) -> Result<Option<(ProxyEntry, BlockingClient)>> {
+        let Some(entry) = self.next() else {
+            return Ok(None);
+        };
+        let client = entry.build_blocking_client()?;
+        Ok(Some((entry, client)))
+    }
+
+    pub fn report_success(&self, url: &Url) {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(entry) = state.proxies.iter_mut().find(|e| &e.url == url) {
+            entry.successes += 1;
+            entry.consecutive_failures = 0;
+        }
+    }
+
+    // returns true if the proxy was evicted
+    pub fn report_failure(&self, url: &Url) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some(i) = state.proxies.iter().position(|e| &e.url == url) else {
+            return false;
+        };
+        state.record_failure(i, self.options.max_failures)
+    }
+
+    // checks check_concurrency proxies at a time, a failed check counts like report_failure.
+    // returns the number of proxies left.
+    pub async fn check(&self) -> usize {
+        let proxies = self.proxies();
+        let results = stream::iter(proxies.iter().map(|e| self.check_proxy(e)))
+            .buffered(self.options.check_concurrency.max(1))
+            .collect::<Vec<_>>()
+            .await;
+        self.apply_checks(&proxies, results)
+    }
+
+    pub fn check_blocking(&self) -> usize {
+        let proxies = self.proxies();
+        let results = Mutex::new(vec![None; proxies.len()]);
+        let next = AtomicUsize::new(0);
+        let workers = self
+            .options
+            .check_concurrency
+            .clamp(1, proxies.len().max(1));
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+
+                    let Some(entry) = proxies.get(i) else {
+                        break;
+                    };
+
+                    let result = self.check_proxy_blocking(entry);
+                    results.lock().unwrap()[i] = result;
+                });
+            }
+        });
+        self.apply_checks(&proxies, results.into_inner().unwrap())
+    }
+
+    async fn check_proxy(&self, entry: &ProxyEntry) -> Option<(String, Duration)> {
+        let client = build_client()
+            .proxy(entry.proxy().ok()?)
+            .timeout(self.options.check_timeout)
+            .build()
+            .ok()?;
+        let start = Instant::now();
+        let response = client
+            .get(&self.options.check_url)
+            .send()
+            .await
+            .ok()?
+            .error_for_status()
+            .ok()?;
+        let text = response.text().await.ok()?;
+        Some((text.trim().to_string(), start.elapsed()))
+    }
+
+    fn check_proxy_blocking(&self, entry: &ProxyEntry) -> Option<(String, Duration)> {
+        let client = build_blocking_client()
+            .proxy(entry.proxy().ok()?)
+            .timeout(self.options.check_timeout)
+            .build()
+            .ok()?;
+        let start = Instant::now();
+        let response = client
+            .get(&self.options.check_url)
+            .send()
+            .ok()?
+            .error_for_status()
+            .ok()?;
+        let text = response.text().ok()?;
+        Some((text.trim().to_string(), start.elapsed()))
+    }
+
+    fn apply_checks(
+        &self,
+        proxies: &[ProxyEntry],
+        results: Vec<Option<(String, Duration)>>,
+    ) -> usize {
+        let mut state = self.state.lock().unwrap();
+
+        for (proxy, result) in proxies.iter().zip(results) {
+            let Some(i) = state.proxies.iter().position(|e| e.url == proxy.url) else {
+                continue;
+            };
+
+            match result {
+                Some((address, latency)) => {
+                    let entry = &mut state.proxies[i];
+                    entry.successes += 1;
+                    entry.consecutive_failures = 0;
+                    entry.latency = Some(latency);
+                    entry.address = Some(address);
+                }
+                None => {
+                    state.record_failure(i, self.options.max_failures);
+                }
+            }
+        }
+
+        state.proxies.len()
+    }
+}
+
+impl Default for ProxyPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// the credentials are masked so the url can be logged
+fn redact(url: &Url) -> Url {
+    let mut url = url.clone();
+
+    if !url.username().is_empty() {
+        let _ = url.set_username("***");
+    }
+
+    if url.password().is_some() {
+        let _ = url.set_password(Some("***"));
+    }
+
+    url
+}
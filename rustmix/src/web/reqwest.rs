@@ -63,6 +63,30 @@ pub fn build_blocking_client_with_headers(
     build_blocking_client().default_headers(headers)
 }
 
+pub fn build_client_with_profile(
+    profile: &super::profile::BrowserProfile,
+) -> _reqwest::ClientBuilder {
+    build_client().default_headers(profile.headers())
+}
+
+pub fn build_blocking_client_with_profile(
+    profile: &super::profile::BrowserProfile,
+) -> _reqwest::blocking::ClientBuilder {
+    build_blocking_client().default_headers(profile.headers())
+}
+
+pub fn build_client_with_cookies(
+    jar: &std::sync::Arc<super::cookies::CookieJar>,
+) -> _reqwest::ClientBuilder {
+    build_client().cookie_provider(jar.clone())
+}
+
+pub fn build_blocking_client_with_cookies(
+    jar: &std::sync::Arc<super::cookies::CookieJar>,
+) -> _reqwest::blocking::ClientBuilder {
+    build_blocking_client().cookie_provider(jar.clone())
+}
+
 pub fn build_client_for_api() -> _reqwest::ClientBuilder {
     build_client().default_headers(build_default_api_headers())
 }
@@ -0,0 +1,589 @@
+use super::{
+    host_key,
+    limiter::RateLimiter,
+    reqwest::{
+        blocking::{
+            Client as BlockingClient, Request as BlockingRequest,
+            RequestBuilder as BlockingRequestBuilder, Response as BlockingResponse,
+        },
+        build_blocking_client, build_client, header, Client, IntoUrl, Method, Request,
+        RequestBuilder, Response, StatusCode,
+    },
+};
+use crate::{
+    error::{BlockedRequestError, NoConnectionError},
+    Result,
+};
+use ::backoff::{backoff::Backoff, ExponentialBackoff, ExponentialBackoffBuilder};
+use chrono::{DateTime, Utc};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+const MAX_RETRIES_DEF: usize = 3;
+const INITIAL_INTERVAL_DEF: Duration = Duration::from_millis(500);
+const MAX_INTERVAL_DEF: Duration = Duration::from_secs(30);
+const MAX_ELAPSED_DEF: Duration = Duration::from_secs(120);
+const BREAKER_THRESHOLD_DEF: u32 = 5;
+const BREAKER_TIMEOUT_DEF: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryOptions {
+    pub max_retries: usize,
+    pub initial_interval: Duration,
+    pub max_interval: Duration,
+    // total time spent retrying, a Retry-After beyond it is not waited for
+    pub max_elapsed: Duration,
+    // consecutive failures that open the circuit of a host, 0 disables the breaker
+    pub breaker_threshold: u32,
+    pub breaker_timeout: Duration,
+}
+
+impl Default for RetryOptions {
+    fn default() -> Self {
+        RetryOptions {
+            max_retries: MAX_RETRIES_DEF,
+            initial_interval: INITIAL_INTERVAL_DEF,
+            max_interval: MAX_INTERVAL_DEF,
+            max_elapsed: MAX_ELAPSED_DEF,
+            breaker_threshold: BREAKER_THRESHOLD_DEF,
+            breaker_timeout: BREAKER_TIMEOUT_DEF,
+        }
+    }
+}
+
+impl RetryOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_max_retries(&self, max_retries: usize) -> Self {
+        RetryOptions {
+            max_retries,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_initial_interval(&self, initial_interval: Duration) -> Self {
+        RetryOptions {
+            initial_interval,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_max_interval(&self, max_interval: Duration) -> Self {
+        RetryOptions {
+            max_interval,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_max_elapsed(&self, max_elapsed: Duration) -> Self {
+        RetryOptions {
+            max_elapsed,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_breaker_threshold(&self, breaker_threshold: u32) -> Self {
+        RetryOptions {
+            breaker_threshold,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_breaker_timeout(&self, breaker_timeout: Duration) -> Self {
+        RetryOptions {
+            breaker_timeout,
+            ..self.clone()
+        }
+    }
+
+    fn backoff(&self) -> ExponentialBackoff {
+        ExponentialBackoffBuilder::new()
+            .with_initial_interval(self.initial_interval)
+            .with_max_interval(self.max_interval)
+            .with_max_elapsed_time(Some(self.max_elapsed))
+            .build()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CircuitState {
+    #[default]
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CircuitState::Closed => write!(f, "Closed"),
+            CircuitState::Open => write!(f, "Open"),
+            CircuitState::HalfOpen => write!(f, "HalfOpen"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Failure {
+    Connection,
+    Status,
+}
+
+#[derive(Debug, Clone)]
+struct HostCircuit {
+    state: CircuitState,
+    failures: u32,
+    changed: Instant,
+    failure: Failure,
+}
+
+impl Default for HostCircuit {
+    fn default() -> Self {
+        HostCircuit {
+            state: CircuitState::Closed,
+            failures: 0,
+            changed: Instant::now(),
+            failure: Failure::Connection,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    timeout: Duration,
+    hosts: Mutex<HashMap<String, HostCircuit>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, timeout: Duration) -> Self {
+        Self {
+            threshold,
+            timeout,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn state<T: AsRef<str>>(&self, host: T) -> CircuitState {
+        let hosts = self.hosts.lock().unwrap();
+        hosts
+            .get(host.as_ref())
+            .map(|e| e.state)
+            .unwrap_or_default()
+    }
+
+    pub fn reset<T: AsRef<str>>(&self, host: T) {
+        self.hosts.lock().unwrap().remove(host.as_ref());
+    }
+
+    pub fn reset_all(&self) {
+        self.hosts.lock().unwrap().clear();
+    }
+
+    // an open circuit lets a single trial request through once the timeout passes
+    fn acquire(&self, host: &str) -> std::result::Result<(), Failure> {
+        if self.threshold == 0 {
+            return Ok(());
+        }
+
+        let mut hosts = self.hosts.lock().unwrap();
+        let Some(circuit) = hosts.get_mut(host) else {
+            return Ok(());
+        };
+
+        match circuit.state {
+            CircuitState::Closed => Ok(()),
+            _ if circuit.changed.elapsed() >= self.timeout => {
+                circuit.state = CircuitState::HalfOpen;
+                circuit.changed = Instant::now();
+                Ok(())
+            }
+            _ => Err(circuit.failure),
+        }
+    }
+
+    fn record_success(&self, host: &str) {
+        if self.threshold == 0 {
+            return;
+        }
+
+        self.hosts.lock().unwrap().remove(host);
+    }
+
+    fn record_failure(&self, host: &str, failure: Failure) {
+        if self.threshold == 0 {
+            return;
+        }
+
+        let mut hosts = self.hosts.lock().unwrap();
+        let circuit = hosts.entry(host.to_string()).or_default();
+        circuit.failures += 1;
+        circuit.failure = failure;
+
+        if circuit.state == CircuitState::HalfOpen || circuit.failures >= self.threshold {
+            circuit.state = CircuitState::Open;
+            circuit.changed = Instant::now();
+        }
+    }
+
+    // an error that says nothing about the host still ends a trial
+    fn record_inconclusive(&self, host: &str) {
+        if self.threshold == 0 {
+            return;
+        }
+
+        let mut hosts = self.hosts.lock().unwrap();
+        let Some(circuit) = hosts.get_mut(host) else {
+            return;
+        };
+
+        if circuit.state == CircuitState::HalfOpen {
+            circuit.state = CircuitState::Open;
+            circuit.changed = Instant::now();
+        }
+    }
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new(BREAKER_THRESHOLD_DEF, BREAKER_TIMEOUT_DEF)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ResilientClient {
+    client: Client,
+    options: RetryOptions,
+    breaker: Arc<CircuitBreaker>,
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl ResilientClient {
+    pub fn new() -> Result<Self> {
+        Self::with_options(&RetryOptions::new())
+    }
+
+    pub fn with_options(options: &RetryOptions) -> Result<Self> {
+        Ok(Self::from_client(build_client().build()?, options))
+    }
+
+    pub fn from_client(client: Client, options: &RetryOptions) -> Self {
+        Self {
+            client,
+            options: options.clone(),
+            breaker: Arc::new(CircuitBreaker::new(
+                options.breaker_threshold,
+                options.breaker_timeout,
+            )),
+            limiter: None,
+        }
+    }
+
+    // every attempt, retries included, waits for a slot of the limiter
+    pub fn with_limiter(&self, limiter: &Arc<RateLimiter>) -> Self {
+        Self {
+            limiter: Some(limiter.clone()),
+            ..self.clone()
+        }
+    }
+
+    pub fn limiter(&self) -> Option<&Arc<RateLimiter>> {
+        self.limiter.as_ref()
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn options(&self) -> &RetryOptions {
+        &self.options
+    }
+
+    pub fn breaker(&self) -> &CircuitBreaker {
+        &self.breaker
+    }
+
+    pub fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    pub fn head<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.client.head(url)
+    }
+
+    pub fn post<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.client.post(url)
+    }
+
+    pub fn put<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.client.put(url)
+    }
+
+    pub fn delete<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.client.delete(url)
+    }
+
+    pub fn request<U: IntoUrl>(&self, method: Method, url: U) -> RequestBuilder {
+        self.client.request(method, url)
+    }
+
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        self.execute(request.build()?).await
+    }
+
+    pub async fn execute(&self, request: Request) -> Result<Response> {
+        let host = host_key(request.url());
+        let retry = is_idempotent(request.method());
+        let mut backoff = self.options.backoff();
+        let mut request = Some(request);
+        let mut attempt = 0usize;
+
+        loop {
+            check_circuit(&self.breaker, &host)?;
+            let (current, rest) = next_attempt(
+                request.take().unwrap(),
+                retry && attempt < self.options.max_retries,
+                |e| e.try_clone(),
+            );
+            request = rest;
+            let permit = match &self.limiter {
+                Some(limiter) => Some(limiter.acquire(&host).await),
+                None => None,
+            };
+            let outcome = self.client.execute(current).await;
+            drop(permit);
+            let hint = match &outcome {
+                Ok(response) => {
+                    assess_status(&self.breaker, &host, response.status(), response.headers())
+                }
+                Err(e) => assess_error(&self.breaker, &host, e),
+            };
+            let delay = match hint {
+                Some(hint) if request.is_some() => next_delay(&mut backoff, hint),
+                _ => None,
+            };
+
+            let Some(delay) = delay else {
+                return outcome.map_err(Into::into);
+            };
+
+            drop(outcome);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockingResilientClient {
+    client: BlockingClient,
+    options: RetryOptions,
+    breaker: Arc<CircuitBreaker>,
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl BlockingResilientClient {
+    pub fn new() -> Result<Self> {
+        Self::with_options(&RetryOptions::new())
+    }
+
+    pub fn with_options(options: &RetryOptions) -> Result<Self> {
+        Ok(Self::from_client(build_blocking_client().build()?, options))
+    }
+
+    pub fn from_client(client: BlockingClient, options: &RetryOptions) -> Self {
+        Self {
+            client,
+            options: options.clone(),
+            breaker: Arc::new(CircuitBreaker::new(
+                options.breaker_threshold,
+                options.breaker_timeout,
+            )),
+            limiter: None,
+        }
+    }
+
+    // every attempt, retries included, waits for a slot of the limiter
+    pub fn with_limiter(&self, limiter: &Arc<RateLimiter>) -> Self {
+        Self {
+            limiter: Some(limiter.clone()),
+            ..self.clone()
+        }
+    }
+
+    pub fn limiter(&self) -> Option<&Arc<RateLimiter>> {
+        self.limiter.as_ref()
+    }
+
+    pub fn client(&self) -> &BlockingClient {
+        &self.client
+    }
+
+    pub fn options(&self) -> &RetryOptions {
+        &self.options
+    }
+
+    pub fn breaker(&self) -> &CircuitBreaker {
+        &self.breaker
+    }
+
+    pub fn get<U: IntoUrl>(&self, url: U) -> BlockingRequestBuilder {
+        self.client.get(url)
+    }
+
+    pub fn head<U: IntoUrl>(&self, url: U) -> BlockingRequestBuilder {
+        self.client.head(url)
+    }
+
+    pub fn post<U: IntoUrl>(&self, url: U) -> BlockingRequestBuilder {
+        self.client.post(url)
+    }
+
+    pub fn put<U: IntoUrl>(&self, url: U) -> BlockingRequestBuilder {
+        self.client.put(url)
+    }
+
+    pub fn delete<U: IntoUrl>(&self, url: U) -> BlockingRequestBuilder {
+        self.client.delete(url)
+    }
+
+    pub fn request<U: IntoUrl>(&self, method: Method, url: U) -> BlockingRequestBuilder {
+        self.client.request(method, url)
+    }
+
+    pub fn send(&self, request: BlockingRequestBuilder) -> Result<BlockingResponse> {
+        self.execute(request.build()?)
+    }
+
+    pub fn execute(&self, request: BlockingRequest) -> Result<BlockingResponse> {
+        let host = host_key(request.url());
+        let retry = is_idempotent(request.method());
+        let mut backoff = self.options.backoff();
+        let mut request = Some(request);
+        let mut attempt = 0usize;
+
+        loop {
+            check_circuit(&self.breaker, &host)?;
+            let (current, rest) = next_attempt(
+                request.take().unwrap(),
+                retry && attempt < self.options.max_retries,
+                |e| e.try_clone(),
+            );
+            request = rest;
+            let permit = self.limiter.as_ref().map(|e| e.acquire_blocking(&host));
+            let outcome = self.client.execute(current);
+            drop(permit);
+            let hint = match &outcome {
+                Ok(response) => {
+                    assess_status(&self.breaker, &host, response.status(), response.headers())
+                }
+                Err(e) => assess_error(&self.breaker, &host, e),
+            };
+            let delay = match hint {
+                Some(hint) if request.is_some() => next_delay(&mut backoff, hint),
+                _ => None,
+            };
+
+            let Some(delay) = delay else {
+                return outcome.map_err(Into::into);
+            };
+
+            drop(outcome);
+            thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+}
+
+pub fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::PUT | Method::DELETE | Method::TRACE
+    )
+}
+
+pub fn retry_after(headers: &header::HeaderMap) -> Option<Duration> {
+    let value = headers.get(header::RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.with_timezone(&Utc) - Utc::now();
+    Some(delay.to_std().unwrap_or_default())
+}
+
+fn check_circuit(breaker: &CircuitBreaker, host: &str) -> Result<()> {
+    match breaker.acquire(host) {
+        Ok(_) => Ok(()),
+        Err(Failure::Connection) => Err(NoConnectionError.into()),
+        Err(Failure::Status) => Err(BlockedRequestError.into()),
+    }
+}
+
+// the original is kept for the next attempt, requests that can't be cloned get a single attempt
+fn next_attempt<R, F: Fn(&R) -> Option<R>>(request: R, retry: bool, clone: F) -> (R, Option<R>) {
+    if !retry {
+        return (request, None);
+    }
+
+    match clone(&request) {
+        Some(current) => (current, Some(request)),
+        None => (request, None),
+    }
+}
+
+// None means done, Some carries the Retry-After of the response if there is one
+fn assess_status(
+    breaker: &CircuitBreaker,
+    host: &str,
+    status: StatusCode,
+    headers: &header::HeaderMap,
+) -> Option<Option<Duration>> {
+    if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+        breaker.record_failure(host, Failure::Status);
+        return Some(retry_after(headers));
+    }
+
+    breaker.record_success(host);
+    None
+}
+
+fn assess_error(
+    breaker: &CircuitBreaker,
+    host: &str,
+    error: &super::reqwest::Error,
+) -> Option<Option<Duration>> {
+    if error.is_connect() || error.is_timeout() {
+        breaker.record_failure(host, Failure::Connection);
+        return Some(None);
+    }
+
+    breaker.record_inconclusive(host);
+    None
+}
+
+fn next_delay(backoff: &mut ExponentialBackoff, retry_after: Option<Duration>) -> Option<Duration> {
+    let delay = backoff.next_backoff()?;
+
+    let Some(retry_after) = retry_after else {
+        return Some(delay);
+    };
+
+    // don't wait on a server that asks for more than the retry budget
+    let budget = backoff.max_elapsed_time.unwrap_or(Duration::MAX);
+
+    if backoff.get_elapsed_time() + retry_after > budget {
+        return None;
+    }
+
+    Some(retry_after)
+}
@@ -27,9 +27,25 @@ async fn main() -> Result<()> {
     //tests::test_path()?;
     //tests::test_directory()?;
     //tests::test_file()?;
+    //tests::test_async_file().await?;
+    //tests::test_data_files()?;
+    //tests::test_atomic_write()?;
+    //tests::test_copy_progress()?;
+    //tests::test_sync()?;
+    //tests::test_watcher().await?;
+    //tests::test_try_path()?;
+    //tests::test_path_resolve()?;
+    //tests::test_single_instance()?;
+    //tests::test_follow()?;
 
     //tests::test_url()?;
     //tests::test_reqwest().await?;
+    //tests::test_resilient_reqwest().await?;
+    //tests::test_rate_limited_reqwest().await?;
+    //tests::test_profile_reqwest().await?;
+    //tests::test_proxy_pool().await?;
+    //tests::test_cookie_jar().await?;
+    //tests::test_download().await?;
     //task::spawn_blocking(move || tests::test_blocking_reqwest().unwrap()).await?;
 
     //tests::test_slog()?;
@@ -44,6 +60,7 @@ async fn main() -> Result<()> {
     //tests::test_producer_consumer(Duration::from_millis(150)).await?;
     //tests::test_injector_worker(Duration::ZERO).await?;
     //tests::test_injector_worker(Duration::from_millis(150)).await?;
+    //tests::test_spinner_async().await?;
 
     //tests::test_rwhisper().await?;
 
@@ -1,15 +1,34 @@
+use futures::StreamExt;
 use rand::{distributions::Alphanumeric, Rng};
 use rustmix::{
     io::{
+        archive,
+        async_file::{self, AsyncFileEx},
         directory,
+        encoding::{DecodePolicy, Encoding},
         file::{self, FileEx},
-        path::{self, IntoPath, PathEx},
+        hash::{self, HashAlgorithm},
+        lock::{self, InstanceGuard, LockMode},
+        path::{
+            self, AsPath, CompareMethod, IntoPath, PathEx, SyncOptions, TransferOptions,
+            TransferProgress,
+        },
+        tail::{self, FollowOptions, RotationNaming},
+        temp::{TempDir, TempOptions},
+        watcher::{WatchEvent, WatchOptions, Watcher},
     },
-    Result,
+    threading::{Consumer, Spinner, TaskDelegation, TaskResult},
+    CallbackHandler, Result,
 };
 use std::{
     io::{stdin, LineWriter, Write},
     path::PathBuf,
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
 use super::*;
@@ -50,9 +69,22 @@ pub fn test_path() -> Result<()> {
         println!("{}", entry.display());
     }
 
+    println!("\nI will walk './files' recursively and list only the audio files, sorted.");
+    let options = path::WalkOptions::new()
+        .with_kind(path::EntryKind::Files)
+        .with_include(&["*.mp3", "*.wav"])
+        .with_sorted(true);
+
+    for entry in path::walk_with("./files", &options)? {
+        match entry {
+            Ok(entry) => println!("{}", entry.path.display()),
+            Err(e) => println!("Error: {}", e),
+        }
+    }
+
     println!("\nI will create a temp folder to test a few things.");
-    let tmpdir = curdir.join("tmp");
-    directory::create(&tmpdir)?;
+    let tmp = TempDir::with_options(&TempOptions::new().with_parent(&curdir).with_prefix("tmp"))?;
+    let tmpdir = tmp.path().to_path_buf();
     println!("Temp folder created.");
 
     println!(
@@ -66,6 +98,44 @@ pub fn test_path() -> Result<()> {
         println!("{}", entry.display());
     }
 
+    println!("\nI will hash the copied files and find the duplicates of './files/audio'.");
+
+    for (path, hash) in
+        hash::hash_match(format!("{}/*.*", tmpdir.display()), HashAlgorithm::Sha256)?
+    {
+        match hash {
+            Ok(hash) => println!("{} {}", hash, path.display()),
+            Err(e) => println!("Error: {} {}", path.display(), e),
+        }
+    }
+
+    let duplicates = hash::find_duplicates(
+        &["./files/audio", tmpdir.to_str().unwrap()],
+        &path::WalkOptions::new(),
+        HashAlgorithm::Blake3,
+    )?;
+
+    for group in duplicates {
+        println!(
+            "{} [{}]",
+            group.original().display(),
+            group.redundant().len()
+        );
+    }
+
+    let archive_path = curdir.join("tmp.zip");
+    println!(
+        "\nI will archive the temp folder to '{}' and list it.",
+        archive_path.display()
+    );
+    archive::create(&archive_path, tmpdir.to_str().unwrap())?;
+
+    for entry in archive::list(&archive_path)? {
+        println!("{} {}", entry.path.display(), entry.size);
+    }
+
+    path::del(&archive_path)?;
+
     let new_folder = tmpdir.join("new_folder");
     println!(
         "\nI will move SOME files to a new folder '{}'.",
@@ -80,8 +150,8 @@ pub fn test_path() -> Result<()> {
     println!("\nI will rename the new folder.");
     path::ren(&new_folder, "new_folder_renamed")?;
 
-    println!("\nI will delete the temp folder.");
-    path::del(&tmpdir)?;
+    println!("\nThe temp folder will be deleted when it goes out of scope.");
+    drop(tmp);
 
     Ok(())
 }
@@ -131,6 +201,21 @@ pub fn test_directory() -> Result<()> {
         println!("{}", &part);
     }
 
+    println!("\nI will summarize the disk usage of './files'.");
+    let usage = directory::usage("./files")?;
+    println!("{}", usage);
+
+    for file in &usage.largest {
+        println!("{} {}", file.size, file.path.display());
+    }
+
+    for extension in &usage.extensions {
+        println!(
+            "{}: {} files, {} bytes",
+            extension.extension, extension.files, extension.size
+        );
+    }
+
     println!("\nI will delete the directory.");
     let path = path.take(original_path_len + 1);
     println!("The path is now '{}'", &path.display());
@@ -188,7 +273,7 @@ pub fn test_file() -> Result<()> {
     let file = file::open(&path)?;
 
     for line in file.read()? {
-        println!("{}", line);
+        println!("{}", line?);
     }
 
     drop(file);
@@ -198,7 +283,7 @@ pub fn test_file() -> Result<()> {
     let file = file::open(&path)?;
 
     for line in file.read_filtered(|e: &str| !e.contains("12345"))? {
-        println!("{}", line);
+        println!("{}", line?);
     }
 
     drop(file);
@@ -229,7 +314,7 @@ pub fn test_file() -> Result<()> {
     let file = file::open(&path)?;
 
     for line in file.read()? {
-        println!("{}", line);
+        println!("{}", line?);
     }
 
     drop(file);
@@ -260,6 +345,19 @@ pub fn test_file() -> Result<()> {
 
     drop(file);
 
+    println!("\nI will write and read it again using the typed csv functions.");
+    let mut file = file::create_with(&path, file::FileOpenOptions::Truncate)?;
+    file.write_csv(&employees, None, None)?;
+    drop(file);
+
+    let file = file::open(&path)?;
+
+    for record in file.read_csv::<Employee>(None, None)? {
+        println!("{:?}", record?);
+    }
+
+    drop(file);
+
     println!("\nI will test writing some tsv.");
     path.set_extension("tsv");
     println!("The path is now '{}'.", path.display());
@@ -283,6 +381,48 @@ pub fn test_file() -> Result<()> {
 
     drop(file);
 
+    println!("\nI will test reading a UTF-16 file.");
+    path.set_extension("txt");
+    println!("The path is now '{}'.", path.display());
+    let mut file = file::create_with(&path, file::FileOpenOptions::Truncate)?;
+    let mut bytes = Encoding::Utf16LE.bom().to_vec();
+
+    for unit in "Hello, world!\r\nHéllo, wörld!\n".encode_utf16() {
+        bytes.extend_from_slice(&unit.to_le_bytes());
+    }
+
+    file.write_all(&bytes)?;
+    drop(file);
+
+    let file = file::open(&path)?;
+    println!("Detected encoding: {}", file.detect_encoding()?);
+
+    for line in file.read_decoded(None, DecodePolicy::Report)? {
+        println!("{}", line?);
+    }
+
+    drop(file);
+
+    println!("\nI will read the last lines and then the file backwards.");
+    let mut file = file::create_with(&path, file::FileOpenOptions::Truncate)?;
+    file.write_lines((1..=25).map(|e| format!("Line {}", e)))?;
+    drop(file);
+
+    let file = file::open(&path)?;
+    println!("{:?}", file.read_last(3)?);
+
+    for line in file.read_rev()?.take(5) {
+        println!("{}", line?);
+    }
+
+    let index = file.index_lines(10)?;
+    println!(
+        "{} lines, line 17 is {:?}",
+        index.len(),
+        index.read_line(&file, 17)?
+    );
+    drop(file);
+
     println!("\nI will delete the directory.");
     let path = path.take(original_path_len + 1);
     println!("The path is now '{}'", &path.display());
@@ -291,6 +431,656 @@ pub fn test_file() -> Result<()> {
     Ok(())
 }
 
+pub async fn test_async_file() -> Result<()> {
+    println!("\nTesting async file functions...");
+
+    let curdir = directory::current();
+    let mut path = curdir.join("My Async Folder/NonEmpty.txt");
+    println!("I will create the file '{}'", &path.display());
+    let mut file = async_file::create_with(&path, file::FileOpenOptions::Truncate).await?;
+    file.write(&"Hello, world!").await?;
+    file.write_lines((0..12).map(|e| format!("Line {}", e)))
+        .await?;
+    drop(file);
+
+    println!("\nI will open the file and read it in batches of 5 lines.");
+    let mut file = async_file::open(&path).await?;
+    file.read_batch(5, print_batch).await?;
+    drop(file);
+
+    println!("\nI will read only the even lines, a read error would end the stream.");
+    let mut file = async_file::open(&path).await?;
+    let mut lines = pin!(file.read_filtered(|e: &str| e.ends_with(['0', '2', '4', '6', '8'])));
+
+    while let Some(line) = lines.next().await {
+        match line {
+            Ok(line) => println!("{}", line),
+            Err(e) => println!("Error: {}", e),
+        }
+    }
+
+    let employees = get_employees(3);
+    println!("\nI will write and read some csv.");
+    path.set_extension("csv");
+    let mut file = async_file::create_with(&path, file::FileOpenOptions::Truncate).await?;
+    file.write_csv(&employees, None, None).await?;
+    drop(file);
+
+    let mut file = async_file::open(&path).await?;
+
+    for record in file.read_csv::<Employee>(None, None).await? {
+        println!("{:?}", record?);
+    }
+
+    drop(file);
+
+    println!("\nI will write and read some json.");
+    path.set_extension("json");
+    let mut file = async_file::create_with(&path, file::FileOpenOptions::Truncate).await?;
+    file.write_json(&employees, Some(true)).await?;
+    drop(file);
+
+    let mut file = async_file::open(&path).await?;
+    let employees: Vec<Employee> = file.read_json().await?;
+    println!("{:?}", employees);
+    drop(file);
+
+    println!("\nI will delete the directory.");
+    delete_dir(&path.parent().unwrap().to_path_buf())?;
+
+    Ok(())
+}
+
+pub fn test_data_files() -> Result<()> {
+    println!("\nTesting JSON Lines, YAML and TOML files...");
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Staff {
+        company: String,
+        employees: Vec<Employee>,
+    }
+
+    let dir = TempDir::new()?;
+    let employees = get_employees(3);
+
+    println!("\nI will write the employees as JSON Lines and append one more.");
+    let path = dir.join("employees.jsonl");
+    let mut file = file::create_with(&path, file::FileOpenOptions::Truncate)?;
+    file.write_jsonl(&employees)?;
+    let extra = get_employees(4).pop().unwrap();
+    file.append_jsonl(&extra)?;
+    drop(file);
+
+    let file = file::open(&path)?;
+
+    for employee in file.read_jsonl::<Employee>()? {
+        println!("{:?}", employee?);
+    }
+
+    drop(file);
+
+    println!("\nI will append a broken line, the reader reports its line number.");
+    let mut file = file::create_with(&path, file::FileOpenOptions::Append)?;
+    writeln!(file, "{{not json}}")?;
+    drop(file);
+
+    let file = file::open(&path)?;
+
+    for employee in file.read_jsonl::<Employee>()? {
+        match employee {
+            Ok(employee) => println!("{:?}", employee),
+            Err(e) => println!("Error: {}", e),
+        }
+    }
+
+    drop(file);
+
+    println!("\nI will save and load the employees in each format by extension.");
+    let staff = Staff {
+        company: "rustmix".to_string(),
+        employees,
+    };
+
+    for name in ["staff.json", "staff.yaml", "staff.toml"] {
+        let path = dir.join(name);
+        file::save(&path, &staff)?;
+        println!(
+            "\n{} ({}):\n{}",
+            name,
+            file::DataFormat::from_path(&path).unwrap(),
+            std::fs::read_to_string(&path)?
+        );
+        let loaded: Staff = file::load(&path)?;
+        println!("Loaded {} employees.", loaded.employees.len());
+    }
+
+    println!("\nJSON Lines round trips a list through load and save.");
+    let path = dir.join("staff.ndjson");
+    file::save(&path, &staff.employees)?;
+    let loaded: Vec<Employee> = file::load(&path)?;
+    println!("Loaded {} employees.", loaded.len());
+
+    println!("\nAn unknown extension is not supported.");
+    match file::load::<Staff, _>(dir.join("staff.ini")) {
+        Ok(_) => println!("Loaded, which was not expected."),
+        Err(e) => println!("Error: {}", e),
+    }
+
+    Ok(())
+}
+
+pub fn test_atomic_write() -> Result<()> {
+    println!("\nTesting atomic writes...");
+
+    let dir = TempDir::new()?;
+    let path = dir.join("employees.json");
+
+    println!("\nI will write the file atomically twice, keeping a backup.");
+    file::write_atomic(&path, true, |file| {
+        file.write_json(&get_employees(3), Some(true))
+    })?;
+    file::write_atomic(&path, true, |file| {
+        file.write_json(&get_employees(1), Some(true))
+    })?;
+    let current: Vec<Employee> = file::load(&path)?;
+    let backup: Vec<Employee> = file::load_as(file::backup_path(&path), file::DataFormat::Json)?;
+    println!(
+        "The file has {} employee(s), the backup has {}.",
+        current.len(),
+        backup.len()
+    );
+
+    println!("\nA failed write leaves the file as it was.");
+    let result = file::write_atomic(&path, false, |file| {
+        file.write_lines(std::iter::once("half written"))?;
+        Err("the write failed".into())
+    });
+
+    if let Err(e) = result {
+        println!("Error: {}", e);
+    }
+
+    let current: Vec<Employee> = file::load(&path)?;
+    println!("The file still has {} employee(s).", current.len());
+
+    println!("\nA commit that cannot rename does not leave the temp file behind.");
+    let target = dir.join("My Folder");
+    directory::ensure(&target)?;
+    let mut file = file::create_atomic(&target)?;
+    file.write_json(&get_employees(2), None)?;
+    let temp = file.temp_path().to_path_buf();
+
+    match file.commit() {
+        Ok(_) => println!("Committed, which was not expected."),
+        Err(e) => println!("Error: {}", e),
+    }
+
+    println!(
+        "Does the temp file exist? {}",
+        if temp.exists() { "Yes" } else { "No" }
+    );
+
+    Ok(())
+}
+
+pub fn test_copy_progress() -> Result<()> {
+    println!("\nTesting copy and move with progress...");
+
+    struct CancelAfter {
+        cancel: Arc<AtomicBool>,
+        bytes: u64,
+    }
+
+    impl CallbackHandler<TransferProgress> for CancelAfter {
+        fn starting(&self) {}
+
+        fn update(&self, data: TransferProgress) {
+            if data.bytes >= self.bytes {
+                self.cancel.store(true, Ordering::SeqCst);
+            }
+        }
+
+        fn completed(&self) {}
+    }
+
+    let dir = TempDir::new()?;
+    let source = dir.join("source");
+    directory::ensure(source.join("nested"))?;
+
+    for i in 1..=4 {
+        std::fs::write(
+            source.join(format!("file{}.bin", i)),
+            vec![b'x'; 1024 * 1024],
+        )?;
+    }
+
+    std::fs::write(source.join("nested/notes.txt"), "Hello, world!")?;
+    #[cfg(unix)]
+    std::os::unix::fs::symlink("nested", source.join("link"))?;
+
+    println!("\nI will copy the folder with a spinner showing the progress.");
+    let options = TransferOptions::new().with_preserve(true, true);
+    let spinner = Spinner::new();
+    let summary = path::cpy_progress(source.as_str(), dir.join("copy"), &options, &spinner)?;
+    println!(
+        "Copied {} entries, {} bytes. Is the link still a link? {}",
+        summary.copied.len(),
+        summary.bytes,
+        if dir.join("copy/source/link").is_symlink() {
+            "Yes"
+        } else {
+            "No"
+        }
+    );
+
+    println!("\nCopying again skips the files that exist.");
+    let summary = path::cpy_cancellable(
+        source.as_str(),
+        dir.join("copy"),
+        &options.with_skip_exist(true),
+    )?;
+    println!("Skipped {} entries.", summary.skipped.len());
+
+    println!("\nWithout overwrite or skip, the files that exist are reported as failed.");
+    let summary = path::cpy_cancellable(source.as_str(), dir.join("copy"), &options)?;
+
+    for (path, error) in summary.failed.iter().take(2) {
+        println!("{}: {}", path.display(), error);
+    }
+
+    println!("\nI will cancel a copy halfway through.");
+    let cancel = Arc::new(AtomicBool::new(false));
+    let handler = CancelAfter {
+        cancel: cancel.clone(),
+        bytes: 2 * 1024 * 1024,
+    };
+    let summary = path::cpy_progress(
+        source.as_str(),
+        dir.join("cancelled"),
+        &options.with_cancel(cancel),
+        &handler,
+    )?;
+    println!(
+        "Cancelled? {}. {} file(s) were copied before it stopped.",
+        if summary.cancelled { "Yes" } else { "No" },
+        summary.copied.len()
+    );
+
+    println!("\nI will move the folder.");
+    let summary = path::mov_cancellable(source.as_str(), dir.join("moved"), &options)?;
+    println!(
+        "Moved {} entries. Does the source still exist? {}",
+        summary.copied.len(),
+        if source.exists() { "Yes" } else { "No" }
+    );
+
+    Ok(())
+}
+
+pub fn test_sync() -> Result<()> {
+    println!("\nTesting directory diff and mirror...");
+
+    let dir = TempDir::new()?;
+    let source = dir.join("source");
+    let target = dir.join("target");
+    directory::ensure(source.join("docs"))?;
+    std::fs::write(source.join("readme.txt"), "Hello, world!")?;
+    std::fs::write(source.join("docs/guide.txt"), "Version 1")?;
+    std::fs::write(source.join("build.log"), "Not synced")?;
+
+    println!("\nI will mirror the folder into an empty target, log files are excluded.");
+    let options = SyncOptions::new().with_exclude(&["*.log"]);
+    let summary = path::mirror(&source, &target, &options)?;
+    println!("Copied: {:?}", summary.copied);
+
+    println!("\nI will change both folders and compare them.");
+    std::fs::write(source.join("docs/guide.txt"), "Version 10")?;
+    std::fs::write(source.join("new.txt"), "New file")?;
+    std::fs::write(target.join("extra.txt"), "Only in the target")?;
+    let diff = path::diff(&source, &target, &options)?;
+    println!("Added: {:?}", diff.added);
+    println!("Modified: {:?}", diff.modified);
+    println!("Removed: {:?}", diff.removed);
+
+    println!("\nA dry run only reports what would change.");
+    let dry_run = options.with_dry_run(true).with_delete_extraneous(true);
+    let summary = path::mirror(&source, &target, &dry_run)?;
+    println!(
+        "Would copy {:?} and remove {:?}",
+        summary.copied, summary.removed
+    );
+
+    println!("\nI will mirror it for real and remove the extra files.");
+    let summary = path::mirror(&source, &target, &options.with_delete_extraneous(true))?;
+    println!(
+        "Copied {:?} and removed {:?}",
+        summary.copied, summary.removed
+    );
+    let diff = path::diff(
+        &source,
+        &target,
+        &options.with_compare(CompareMethod::Content),
+    )?;
+    println!(
+        "Are the folders the same now? {}",
+        if diff.is_empty() { "Yes" } else { "No" }
+    );
+
+    #[cfg(unix)]
+    {
+        println!("\nLinks are compared and mirrored by where they point, not by their content.");
+        std::os::unix::fs::symlink("readme.txt", source.join("latest"))?;
+        std::os::unix::fs::symlink("new.txt", target.join("latest"))?;
+        std::fs::write(source.join("readme.txt"), "Hello again!!")?;
+        let content = options.with_compare(CompareMethod::Content);
+        let diff = path::diff(&source, &target, &content)?;
+        println!("Modified: {:?}", diff.modified);
+        path::mirror(&source, &target, &content)?;
+        println!(
+            "The target link points to {}",
+            std::fs::read_link(target.join("latest"))?.display()
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn test_watcher() -> Result<()> {
+    println!("\nTesting the file watcher...");
+
+    #[derive(Debug, Clone)]
+    struct EventHandler;
+
+    impl TaskDelegation<Consumer<WatchEvent>, WatchEvent> for EventHandler {
+        fn on_started(&self, _pc: &Consumer<WatchEvent>) {
+            println!("Consumer started");
+        }
+
+        fn process(&self, _pc: &Consumer<WatchEvent>, item: &WatchEvent) -> Result<TaskResult> {
+            println!("Consumed {}", item);
+            Ok(TaskResult::Success)
+        }
+
+        fn on_completed(
+            &self,
+            _pc: &Consumer<WatchEvent>,
+            _item: &WatchEvent,
+            _result: &TaskResult,
+        ) -> bool {
+            true
+        }
+
+        fn on_cancelled(&self, _pc: &Consumer<WatchEvent>) {
+            println!("Consumer cancelled");
+        }
+
+        fn on_finished(&self, _pc: &Consumer<WatchEvent>) {
+            println!("Consumer finished");
+        }
+    }
+
+    async fn print_events(watcher: &mut Watcher) -> Result<()> {
+        while let Ok(Some(event)) =
+            tokio::time::timeout(Duration::from_secs(1), watcher.recv()).await
+        {
+            println!("{}", event?);
+        }
+
+        Ok(())
+    }
+
+    let dir = TempDir::new()?;
+    let options = WatchOptions::new()
+        .with_debounce(Duration::from_millis(200))
+        .with_patterns(&["*.txt"]);
+    let mut watcher = Watcher::with_options(&options)?;
+    watcher.watch(dir.path())?;
+
+    println!("\nI will create a text file and a log file, only the text file matches.");
+    let path = dir.join("notes.txt");
+    std::fs::write(&path, "Hello, world!")?;
+    std::fs::write(dir.join("build.log"), "Not watched")?;
+    print_events(&mut watcher).await?;
+
+    println!("\nI will rename the text file and then delete it.");
+    let renamed = dir.join("renamed.txt");
+    std::fs::rename(&path, &renamed)?;
+    print_events(&mut watcher).await?;
+    std::fs::remove_file(&renamed)?;
+    print_events(&mut watcher).await?;
+    drop(watcher);
+
+    println!("\nI will feed the events to a consumer and then complete it.");
+    let consumer = Consumer::<WatchEvent>::new();
+    consumer.start(&EventHandler)?;
+    let mut watcher = Watcher::with_options(&options)?;
+    watcher.watch(dir.path())?;
+    let feed = watcher.feed(&consumer);
+    std::fs::write(dir.join("fed.txt"), "Hello, consumer!")?;
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    consumer.complete();
+    consumer.wait_async().await?;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    println!(
+        "Did the feed stop with the consumer? {}",
+        if feed.is_finished() { "Yes" } else { "No" }
+    );
+
+    Ok(())
+}
+
+pub fn test_try_path() -> Result<()> {
+    println!("\nTesting the fallible path functions...");
+    let curdir = directory::try_current()?;
+    println!("Current folder: {}", curdir.try_as_str()?);
+
+    let path = (curdir.as_str(), "files", "audio").try_as_full_path()?;
+    println!("Full path: {}", path);
+    println!("Full path: {}", path::try_get_full_path("./files")?);
+
+    let dir = TempDir::new()?;
+    std::fs::write(dir.join("file.txt"), "Hello, world!")?;
+
+    println!("\nI will list a folder, a file and a missing path.");
+
+    for path in [
+        dir.path().to_path_buf(),
+        dir.join("file.txt"),
+        dir.join("missing"),
+    ] {
+        match path::try_lst(&path) {
+            Ok(entries) => println!("{} entries: {}", path.display(), entries.count()),
+            Err(e) => println!("Error: {}", e),
+        }
+    }
+
+    #[cfg(unix)]
+    {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        println!("\nA name that is not valid UTF-8 fails instead of turning into an empty string.");
+        let path = dir.join(OsStr::from_bytes(b"invalid-\xff.txt"));
+        println!("as_str: '{}'", path.as_str());
+
+        match path.try_as_str() {
+            Ok(path) => println!("try_as_str: '{}'", path),
+            Err(e) => println!("Error: {}", e),
+        }
+
+        println!(
+            "The OsStr variants keep the name: {:?} {:?}",
+            path::name_os(&path).unwrap_or_default(),
+            path::extension_os(&path).unwrap_or_default()
+        );
+    }
+
+    Ok(())
+}
+
+pub fn test_path_resolve() -> Result<()> {
+    println!("\nTesting path normalization and expansion...");
+    let path = PathBuf::from("./files/../files/./audio/");
+    println!(
+        "{} => {}",
+        path.display(),
+        path.lexical_normalize().display()
+    );
+
+    let relative = PathBuf::from("/data/files/audio").relative_to("/data/logs")?;
+    println!("Relative to /data/logs: {}", relative.display());
+
+    println!("\nI will expand the home folder and the environment variables.");
+    std::env::set_var("RUSTMIX_DEMO", "demo");
+
+    for path in [
+        "~/Documents",
+        "$RUSTMIX_DEMO/file.txt",
+        "${RUSTMIX_DEMO}_backup",
+    ] {
+        match path.expand() {
+            Ok(expanded) => println!("{} => {}", path, expanded.display()),
+            Err(e) => println!("Error: {}", e),
+        }
+    }
+
+    println!("\nI will join untrusted names to a root folder.");
+    let root = PathBuf::from("/srv/uploads");
+
+    for name in [
+        "images/logo.png",
+        "images/../notes.txt",
+        "../etc/passwd",
+        "/etc/passwd",
+    ] {
+        match root.safe_join(name) {
+            Ok(path) => println!("{} => {}", name, path.display()),
+            Err(e) => println!("Error: {}", e),
+        }
+    }
+
+    Ok(())
+}
+
+pub fn test_single_instance() -> Result<()> {
+    println!("\nTesting the single instance guard and file locks...");
+
+    let dir = TempDir::new()?;
+    let path = dir.join("app.pid");
+    let guard = InstanceGuard::acquire(&path)?;
+    println!(
+        "Acquired {} for PID {}.",
+        guard.path().display(),
+        lock::read_pid(&path).unwrap_or_default()
+    );
+
+    println!("\nA second instance is refused while the first one is running.");
+
+    match InstanceGuard::acquire(&path) {
+        Ok(_) => println!("Acquired, which was not expected."),
+        Err(e) => println!("Error: {}", e),
+    }
+
+    guard.release()?;
+    println!(
+        "Released. Is the PID still there? {}",
+        if lock::read_pid(&path).is_some() {
+            "Yes"
+        } else {
+            "No"
+        }
+    );
+    let guard = InstanceGuard::acquire(&path)?;
+    println!("Acquired again, stale PID: {:?}", guard.stale_pid());
+    drop(guard);
+
+    println!("\nI will hold a file lock in another thread and wait for it with a timeout.");
+    let lock_path = dir.join("data.lock");
+    file::create(&lock_path)?;
+    let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+    let holder = {
+        let lock_path = lock_path.clone();
+        std::thread::spawn(move || -> std::io::Result<()> {
+            let file = std::fs::File::open(&lock_path)?;
+            file.lock()?;
+            let _ = locked_tx.send(());
+            std::thread::sleep(Duration::from_millis(300));
+            file.unlock()
+        })
+    };
+    let _ = locked_rx.recv();
+    let file = std::fs::File::open(&lock_path)?;
+
+    match file.acquire_lock_timeout(LockMode::Exclusive, Duration::from_millis(50)) {
+        Ok(_) => println!("Locked, which was not expected."),
+        Err(e) => println!("Error: {}", e),
+    }
+
+    let lock = file.acquire_lock_timeout(LockMode::Exclusive, Duration::from_secs(2))?;
+    println!("Locked after the other thread let go: {}", lock.mode());
+    drop(lock);
+    let _ = holder.join();
+
+    Ok(())
+}
+
+pub fn test_follow() -> Result<()> {
+    println!("\nTesting following a log file...");
+
+    let dir = TempDir::new()?;
+    let path = dir.join("app.log");
+    let append = |path: &PathBuf, lines: &[&str]| -> std::io::Result<()> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        lines.iter().try_for_each(|line| writeln!(file, "{}", line))
+    };
+    let print_lines = |follower: &mut tail::Follower| -> Result<()> {
+        while let Some(line) = follower.try_next()? {
+            println!("{}", line);
+        }
+
+        Ok(())
+    };
+
+    append(&path, &["line 1", "line 2"])?;
+    let options = FollowOptions::new()
+        .with_from_start(true)
+        .with_naming(RotationNaming::AppendCount);
+    let mut follower = tail::follow_with(&path, &options)?;
+    println!("\nI will read what is already in the file.");
+    print_lines(&mut follower)?;
+
+    println!("\nI will append to the file and read only the new lines.");
+    append(&path, &["line 3"])?;
+    print_lines(&mut follower)?;
+
+    println!("\nI will rotate the file, the rest of the old file is read before the new one.");
+    append(&path, &["line 4"])?;
+    std::fs::rename(&path, dir.join("app.log.1"))?;
+    append(&path, &["line 5"])?;
+    print_lines(&mut follower)?;
+
+    println!("\nI will rotate it twice between reads, no generation is missed.");
+    append(&path, &["line 6"])?;
+    std::fs::rename(dir.join("app.log.1"), dir.join("app.log.2"))?;
+    std::fs::rename(&path, dir.join("app.log.1"))?;
+    append(&path, &["line 7"])?;
+    std::fs::rename(dir.join("app.log.2"), dir.join("app.log.3"))?;
+    std::fs::rename(dir.join("app.log.1"), dir.join("app.log.2"))?;
+    std::fs::rename(&path, dir.join("app.log.1"))?;
+    append(&path, &["line 8"])?;
+    print_lines(&mut follower)?;
+
+    println!("\nI will truncate the file in place, it is read from the start again.");
+    std::fs::write(&path, "")?;
+    print_lines(&mut follower)?;
+    append(&path, &["line 9"])?;
+    print_lines(&mut follower)?;
+
+    Ok(())
+}
+
 fn delete_dir(path: &PathBuf) -> Result<()> {
     print!("Do you want to delete the directory? (y/n): ");
     std::io::stdout().flush()?;
@@ -289,3 +289,55 @@ pub async fn test_injector_worker(cancel_after: Duration) -> Result<()> {
     println!("Elapsed time: {:?}", now.elapsed());
     Ok(())
 }
+
+pub async fn test_spinner_async() -> Result<()> {
+    println!("\nTesting Spinner::run_async...");
+
+    let spinner = Spinner::with_prefix("success: ".to_string());
+    let value = spinner
+        .run_async(
+            |handle| async move {
+                for i in 1..=3 {
+                    handle.set_message(format!("step {i}/3"));
+                    tokio::time::sleep(Duration::from_millis(300)).await;
+                }
+
+                handle.set_message("done");
+                Ok(42)
+            },
+            None,
+        )
+        .await?;
+    println!("result: {value}");
+
+    let spinner = Spinner::with_prefix("failure: ".to_string());
+    let result = spinner
+        .run_async(
+            |handle| async move {
+                handle.set_message("working...");
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                Err::<(), _>(rustmix::error::InvalidOperationError("Something broke".into()).into())
+            },
+            None,
+        )
+        .await;
+    println!("failure: {:?}", result.map_err(|e| e.to_string()));
+
+    let spinner = Spinner::with_prefix("timeout: ".to_string());
+    let result = spinner
+        .run_async(
+            |handle| async move {
+                handle.set_message("waiting forever...");
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                Ok(())
+            },
+            Some(Duration::from_millis(500)),
+        )
+        .await;
+    println!("timeout: {:?}", result.map_err(|e| e.to_string()));
+
+    // a finished spinner cannot run again
+    let result = spinner.run_async(|_| async { Ok(()) }, None).await;
+    println!("rerun: {:?}", result.map_err(|e| e.to_string()));
+    Ok(())
+}